@@ -0,0 +1,691 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crossterm::{event::KeyCode, style};
+
+use crate::file::{self, FileError};
+use crate::layers;
+use crate::paint;
+use crate::palette;
+use crate::render;
+use crate::variables;
+use crate::viewport;
+use crate::window;
+
+/// Width of ANSI export window, border included
+const WIDTH: u16 = 49;
+
+/// Height of ANSI export window, border included
+const HEIGHT: u16 = 8;
+
+/// Extension of ANSI art files
+pub const EXTENSION: &str = "ans";
+
+/// Colors escape sequences can use, not every terminal knows all of them
+#[derive(Clone, Copy, PartialEq)]
+pub enum AnsiColors {
+    /// 8 colors and their bright versions, `ESC[31m`
+    Ansi16,
+    /// Colors of 256-color palette, `ESC[38;5;Nm`
+    Ansi256,
+    /// Any RGB color, `ESC[38;2;R;G;Bm`
+    Truecolor,
+}
+
+impl AnsiColors {
+    fn label(&self) -> &'static str {
+        match self {
+            AnsiColors::Ansi16 => "16",
+            AnsiColors::Ansi256 => "256",
+            AnsiColors::Truecolor => "truecolor (RGB)",
+        }
+    }
+
+    fn next(&self) -> AnsiColors {
+        match self {
+            AnsiColors::Ansi16 => AnsiColors::Ansi256,
+            AnsiColors::Ansi256 => AnsiColors::Truecolor,
+            AnsiColors::Truecolor => AnsiColors::Ansi16,
+        }
+    }
+}
+
+/// Options of ANSI art export and opening, they stay the same until GVPaint is closed
+pub struct AnsiOptions {
+    pub colors: AnsiColors,
+    /// Two blocks in a cell with `▀` and `▄`, otherwise a block is a single `█`.
+    /// Files are opened the same way, every cell is two blocks then
+    pub half_blocks: bool,
+}
+
+/// Number of ANSI color, if it's one of 16 terminal colors
+fn ansi_index(color: style::Color) -> Option<u8> {
+    match color {
+        style::Color::AnsiValue(n) if n < 16 => Some(n),
+        _ => (0..16).find(|i| palette::ansi_color(*i) == color),
+    }
+}
+
+/// Index of color in `candidates` closest to given one
+fn nearest(color: style::Color, candidates: impl Iterator<Item = u8>) -> u8 {
+    let [r, g, b] = file::crossterm_to_image_color(color).0.map(|c| c as i32);
+    let distance = |index: &u8| {
+        let [cr, cg, cb] = file::crossterm_to_image_color(palette::color_256(*index))
+            .0
+            .map(|c| c as i32);
+        (cr - r).pow(2) + (cg - g).pow(2) + (cb - b).pow(2)
+    };
+
+    candidates.min_by_key(distance).unwrap_or_default()
+}
+
+/// Parameters of SGR sequence for a color, `background` is a color behind the character
+fn color_code(color: style::Color, colors: AnsiColors, background: bool) -> String {
+    let base = if background { 40 } else { 30 };
+
+    match colors {
+        AnsiColors::Ansi16 => {
+            let index = ansi_index(color).unwrap_or_else(|| nearest(color, 0..16));
+            if index < 8 {
+                format!("{}", base + index)
+            } else {
+                format!("{}", base + 60 + index - 8)
+            }
+        }
+        AnsiColors::Ansi256 => {
+            /* terminals pick their own RGB for the first 16, so they're left for exact matches */
+            let index = match color {
+                style::Color::AnsiValue(n) => n,
+                _ => ansi_index(color).unwrap_or_else(|| nearest(color, 16..=255)),
+            };
+            format!("{};5;{}", base + 8, index)
+        }
+        AnsiColors::Truecolor => {
+            let [r, g, b] = file::crossterm_to_image_color(color).0;
+            format!("{};2;{};{};{}", base + 8, r, g, b)
+        }
+    }
+}
+
+/// Text of a single line, colors are set only when they change and reset at its end
+fn ansi_line(
+    cells: &[(char, Option<style::Color>, Option<style::Color>)],
+    colors: AnsiColors,
+) -> String {
+    let mut line = String::new();
+    let mut current = (None, None);
+
+    for (character, foreground, background) in cells {
+        if (*foreground, *background) != current {
+            let mut codes = vec!["0".to_string()];
+            codes.extend(foreground.map(|c| color_code(c, colors, false)));
+            codes.extend(background.map(|c| color_code(c, colors, true)));
+
+            line += &format!("\x1b[{}m", codes.join(";"));
+            current = (*foreground, *background);
+        }
+        line.push(*character);
+    }
+
+    if current != (None, None) {
+        line += "\x1b[0m";
+    }
+    line
+}
+
+/// Write a painting as text with escape sequences, canvas is of given size.
+/// Empty cells at the end of lines and empty lines at the end are left out
+pub fn painting_to_ansi(
+    placed: &BTreeMap<(u32, u32), style::Color>,
+    width: u32,
+    height: u32,
+    options: &AnsiOptions,
+) -> String {
+    let block = |x: u32, y: u32| {
+        if y < height {
+            placed.get(&(x, y)).copied()
+        } else {
+            None
+        }
+    };
+
+    let rows = if options.half_blocks {
+        height.div_ceil(2)
+    } else {
+        height
+    };
+    let mut lines: Vec<String> = Vec::new();
+
+    for row in 0..rows {
+        let mut cells = Vec::new();
+
+        for x in 0..width {
+            let cell = if options.half_blocks {
+                match (block(x, row * 2), block(x, row * 2 + 1)) {
+                    (Some(top), Some(bottom)) if top == bottom => ('█', Some(top), None),
+                    (Some(top), bottom) => ('▀', Some(top), bottom),
+                    (None, Some(bottom)) => ('▄', Some(bottom), None),
+                    (None, None) => (' ', None, None),
+                }
+            } else {
+                match block(x, row) {
+                    Some(color) => ('█', Some(color), None),
+                    None => (' ', None, None),
+                }
+            };
+            cells.push(cell);
+        }
+
+        let used = cells
+            .iter()
+            .rposition(|cell| cell.1.is_some())
+            .map_or(0, |i| i + 1);
+        lines.push(ansi_line(&cells[..used], options.colors));
+    }
+
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+
+    lines.iter().map(|line| line.clone() + "\n").collect()
+}
+
+/// Characters of a file, CP437 files of old ANSI art are converted.
+/// SAUCE record after end of file character is left out
+fn decode(bytes: &[u8]) -> Vec<char> {
+    let end = bytes.iter().position(|b| *b == 0x1a).unwrap_or(bytes.len());
+    let bytes = &bytes[..end];
+
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return text.chars().collect();
+    }
+
+    bytes
+        .iter()
+        .map(|b| match b {
+            0xdb => '█',
+            0xdc => '▄',
+            0xdf => '▀',
+            0x00..=0x7f => *b as char,
+            _ => '?',
+        })
+        .collect()
+}
+
+/// Colors set by SGR sequence, `None` is terminal's default
+#[derive(Default)]
+struct Pen {
+    foreground: Option<style::Color>,
+    background: Option<style::Color>,
+    /// Bold text makes first 8 colors bright in old ANSI art
+    bold: bool,
+}
+
+impl Pen {
+    /// Apply parameters of SGR sequence, `ESC[` and `m` are already left out
+    fn apply(&mut self, parameters: &str) {
+        let numbers: Vec<u16> = parameters
+            .split(';')
+            .map(|n| n.parse().unwrap_or(0))
+            .collect();
+        let mut i = 0;
+
+        while i < numbers.len() {
+            let n = numbers[i];
+            match n {
+                0 => *self = Pen::default(),
+                1 => self.bold = true,
+                22 => self.bold = false,
+                30..=37 => self.foreground = Some(palette::ansi_color(n as u8 - 30)),
+                39 => self.foreground = None,
+                40..=47 => self.background = Some(palette::ansi_color(n as u8 - 40)),
+                49 => self.background = None,
+                90..=97 => self.foreground = Some(palette::ansi_color(n as u8 - 90 + 8)),
+                100..=107 => self.background = Some(palette::ansi_color(n as u8 - 100 + 8)),
+                38 | 48 => {
+                    let color = match numbers.get(i + 1) {
+                        Some(5) => {
+                            let index = numbers.get(i + 2).copied().unwrap_or(0);
+                            i += 2;
+                            palette::color_256(index.min(255) as u8)
+                        }
+                        Some(2) => {
+                            let channel =
+                                |j: usize| numbers.get(i + j).copied().unwrap_or(0).min(255) as u8;
+                            let color = style::Color::Rgb {
+                                r: channel(2),
+                                g: channel(3),
+                                b: channel(4),
+                            };
+                            i += 4;
+                            color
+                        }
+                        _ => {
+                            i += 1;
+                            continue;
+                        }
+                    };
+
+                    if n == 38 {
+                        self.foreground = Some(color);
+                    } else {
+                        self.background = Some(color);
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    /// Color of characters, bold makes dark ANSI colors bright
+    fn foreground(&self) -> Option<style::Color> {
+        match self.foreground.and_then(ansi_index) {
+            Some(index) if self.bold && index < 8 => Some(palette::ansi_color(index + 8)),
+            _ => self.foreground,
+        }
+    }
+}
+
+/// Read blocks from text with escape sequences. With `half_blocks` every cell is two blocks,
+/// the upper and lower half, otherwise it's a single one taken from its upper half.
+/// `█` is a block of text color and other characters one of background color, if it's set.
+/// Blocks past the largest canvas are left out
+pub fn parse_ansi(bytes: &[u8], half_blocks: bool) -> file::Blocks {
+    let characters = decode(bytes);
+
+    let mut blocks = Vec::new();
+    let mut pen = Pen::default();
+    let (mut x, mut y) = (0u32, 0u32);
+    let mut i = 0;
+
+    while i < characters.len() {
+        let character = characters[i];
+        i += 1;
+
+        match character {
+            '\x1b' if characters.get(i) == Some(&'[') => {
+                /* CSI sequence, parameters end with a letter */
+                let start = i + 1;
+                let Some(end) =
+                    (start..characters.len()).find(|j| characters[*j].is_ascii_alphabetic())
+                else {
+                    break;
+                };
+                let parameters: String = characters[start..end].iter().collect();
+                i = end + 1;
+
+                match characters[end] {
+                    'm' => pen.apply(&parameters),
+                    'C' => {
+                        let columns = parameters.parse::<u32>().unwrap_or(1).max(1);
+                        x = x.saturating_add(columns).min(file::MAX_CANVAS);
+                    }
+                    _ => {}
+                }
+            }
+            '\n' => {
+                x = 0;
+                y = (y + 1).min(file::MAX_CANVAS);
+            }
+            '\r' => x = 0,
+            c if c.is_control() => {}
+            c => {
+                let (top, bottom) = match c {
+                    '█' => (pen.foreground(), pen.foreground()),
+                    '▀' => (pen.foreground(), pen.background),
+                    '▄' => (pen.background, pen.foreground()),
+                    _ => (pen.background, pen.background),
+                };
+
+                let inside = |row: u32| x < file::MAX_CANVAS && row < file::MAX_CANVAS;
+                if half_blocks {
+                    let row = y * 2;
+                    blocks.extend(top.filter(|_| inside(row)).map(|color| ((x, row), color)));
+                    blocks.extend(
+                        bottom
+                            .filter(|_| inside(row + 1))
+                            .map(|color| ((x, row + 1), color)),
+                    );
+                } else {
+                    blocks.extend(top.filter(|_| inside(y)).map(|color| ((x, y), color)));
+                }
+                x = (x + 1).min(file::MAX_CANVAS);
+            }
+        }
+    }
+
+    blocks
+}
+
+/// Read ANSI art file, with `half_blocks` there are two blocks in every cell
+pub fn read_ansi(path: &Path, half_blocks: bool) -> Result<file::Blocks, FileError> {
+    let bytes = fs::read(path).map_err(|e| FileError::Read(path.into(), e))?;
+    Ok(parse_ansi(&bytes, half_blocks))
+}
+
+/// Save a painting to ANSI art file, canvas is of given size
+pub fn save_ansi(
+    path: &Path,
+    placed: &BTreeMap<(u32, u32), style::Color>,
+    width: u32,
+    height: u32,
+    options: &AnsiOptions,
+) -> Result<(), FileError> {
+    fs::write(path, painting_to_ansi(placed, width, height, options))
+        .map_err(|e| FileError::Write(path.into(), e))
+}
+
+/// Check if a file should be treated as ANSI art
+pub fn is_ansi(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case(EXTENSION))
+}
+
+/// Ask which colors and characters ANSI art should use before it's saved.
+/// When it's opened, only characters can be chosen
+pub fn ansi_window(
+    state: &mut variables::State,
+    file_menu: &mut variables::FileMenu,
+    path: &Path,
+    open: bool,
+) {
+    file_menu.ansi = Some(PathBuf::from(path));
+    file_menu.ansi_open = open;
+    state.window_open = true;
+    state.window_open_name = "ansi".to_string();
+}
+
+/// Position of window's top left corner
+fn origin(viewport: &viewport::Viewport) -> (u16, u16) {
+    (4, viewport.height.saturating_sub(HEIGHT + 2).max(1))
+}
+
+/// Draw ANSI window with current options
+pub fn draw_ansi_window(
+    renderer: &mut render::Renderer,
+    viewport: &viewport::Viewport,
+    file_menu: &variables::FileMenu,
+) {
+    let Some(path) = file_menu.ansi.as_ref() else {
+        return;
+    };
+
+    let (x, y) = origin(viewport);
+    let options = &file_menu.ansi_options;
+    window::draw_border(renderer, x, y, WIDTH, HEIGHT);
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let title: String = if file_menu.ansi_open {
+        format!("Open ANSI art: {}", name)
+    } else {
+        format!("Save ANSI art: {}", name)
+    };
+    let title: String = title.chars().take(WIDTH as usize - 4).collect();
+    window::print_lines(renderer, x + 2, y + 1, &[&title], style::Color::Red);
+
+    let characters = if options.half_blocks {
+        "characters: ▀ ▄, two blocks in a cell"
+    } else {
+        "characters: █, a block in every cell"
+    };
+
+    /* colors are chosen only when saving */
+    let (lines, help) = if file_menu.ansi_open {
+        (
+            vec![format!("1 - {}", characters)],
+            "1 - change, Enter - open, Esc - cancel",
+        )
+    } else {
+        (
+            vec![
+                format!("1 - colors: {}", options.colors.label()),
+                format!("2 - {}", characters),
+            ],
+            "1 - 2 - change, Enter - save, Esc - cancel",
+        )
+    };
+    let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+
+    window::print_lines(renderer, x + 2, y + 3, &lines, style::Color::DarkYellow);
+    window::print_lines(
+        renderer,
+        x + 2,
+        y + HEIGHT - 2,
+        &[help],
+        style::Color::DarkGreen,
+    );
+}
+
+/// Change an option, 0 is the first line of options. Only characters are listed when opening
+fn toggle_option(file_menu: &mut variables::FileMenu, line: u16) {
+    let options = &mut file_menu.ansi_options;
+    let line = line + file_menu.ansi_open as u16;
+
+    match line {
+        0 => options.colors = options.colors.next(),
+        1 => options.half_blocks = !options.half_blocks,
+        _ => {}
+    }
+}
+
+/// Save current frame to ANSI art file waiting in export window
+fn save(
+    canvas: &variables::Canvas,
+    runtime: &variables::Runtime,
+    state: &mut variables::State,
+    file_menu: &mut variables::FileMenu,
+) {
+    state.window_open = false;
+    let Some(path) = file_menu.ansi.take() else {
+        return;
+    };
+
+    let result = save_ansi(
+        &path,
+        &runtime.layers.flatten(runtime.layers.frame),
+        canvas.width as u32,
+        canvas.height as u32,
+        &file_menu.ansi_options,
+    );
+
+    if let Err(e) = result {
+        window::message_box(state, "Error", &e.to_string());
+    }
+}
+
+/// Handle a key pressed while ANSI window is open
+pub fn ansi_input(
+    canvas: &mut variables::Canvas,
+    runtime: &mut variables::Runtime,
+    state: &mut variables::State,
+    file_menu: &mut variables::FileMenu,
+    code: KeyCode,
+) {
+    match code {
+        KeyCode::Esc => {
+            file_menu.ansi = None;
+            state.window_open = false;
+        }
+        KeyCode::Enter if file_menu.ansi_open => open(canvas, runtime, state, file_menu),
+        KeyCode::Enter => save(canvas, runtime, state, file_menu),
+        KeyCode::Char('1') => toggle_option(file_menu, 0),
+        KeyCode::Char('2') if !file_menu.ansi_open => toggle_option(file_menu, 1),
+        _ => {}
+    }
+}
+
+/// Handle a mouse click while ANSI window is open, clicked option is changed
+pub fn ansi_click(
+    viewport: &viewport::Viewport,
+    file_menu: &mut variables::FileMenu,
+    column: u16,
+    row: u16,
+) {
+    let (x, y) = origin(viewport);
+    if column <= x || column >= x + WIDTH - 1 || row < y + 3 {
+        return;
+    }
+
+    toggle_option(file_menu, row - y - 3);
+}
+
+/// Open ANSI art file waiting in ANSI window, canvas grows if it doesn't fit in it.
+/// Nothing is opened into a locked layer
+fn open(
+    canvas: &mut variables::Canvas,
+    runtime: &mut variables::Runtime,
+    state: &mut variables::State,
+    file_menu: &mut variables::FileMenu,
+) {
+    state.window_open = false;
+    let Some(path) = file_menu.ansi.take() else {
+        return;
+    };
+    if layers::check_locked(runtime, state) {
+        return;
+    }
+
+    match read_ansi(&path, file_menu.ansi_options.half_blocks) {
+        Ok(blocks) => {
+            paint::grow_canvas(canvas, file::extent(&blocks));
+            paint::place_blocks(runtime, blocks);
+        }
+        Err(e) => window::message_box(state, "Error", &e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Placed = BTreeMap<(u32, u32), style::Color>;
+
+    /// Save blocks and read them back
+    fn round_trip(placed: &Placed, colors: AnsiColors, half_blocks: bool) -> Placed {
+        let options = AnsiOptions {
+            colors,
+            half_blocks,
+        };
+        let text = painting_to_ansi(placed, 6, 4, &options);
+
+        parse_ansi(text.as_bytes(), half_blocks)
+            .into_iter()
+            .collect()
+    }
+
+    /// Blocks with gaps between them, every cell of half blocks is in a different state
+    fn painting(colors: &[style::Color]) -> Placed {
+        let color = |i: usize| colors[i % colors.len()];
+
+        [
+            ((0, 0), color(0)),
+            ((0, 1), color(0)),
+            ((1, 0), color(1)),
+            ((2, 1), color(2)),
+            ((3, 0), color(3)),
+            ((3, 1), color(4)),
+            ((5, 3), color(5)),
+        ]
+        .into()
+    }
+
+    #[test]
+    fn ansi_16_round_trip() {
+        let colors: Vec<style::Color> = (0..16).map(palette::ansi_color).collect();
+        let placed = painting(&colors[4..]);
+
+        assert_eq!(round_trip(&placed, AnsiColors::Ansi16, false), placed);
+        assert_eq!(round_trip(&placed, AnsiColors::Ansi16, true), placed);
+    }
+
+    #[test]
+    fn ansi_256_round_trip() {
+        let colors: Vec<style::Color> = [9, 16, 100, 196, 232, 255]
+            .into_iter()
+            .map(palette::color_256)
+            .collect();
+        let placed = painting(&colors);
+
+        assert_eq!(round_trip(&placed, AnsiColors::Ansi256, false), placed);
+        assert_eq!(round_trip(&placed, AnsiColors::Ansi256, true), placed);
+    }
+
+    #[test]
+    fn truecolor_round_trip() {
+        let colors: Vec<style::Color> = (0..6)
+            .map(|i| style::Color::Rgb {
+                r: i * 40,
+                g: 255 - i,
+                b: 7 * i,
+            })
+            .collect();
+        let placed = painting(&colors);
+
+        assert_eq!(round_trip(&placed, AnsiColors::Truecolor, false), placed);
+        assert_eq!(round_trip(&placed, AnsiColors::Truecolor, true), placed);
+    }
+
+    #[test]
+    fn colors_are_reduced_to_nearest() {
+        let placed: Placed = [((0, 0), style::Color::Rgb { r: 250, g: 2, b: 3 })].into();
+
+        let parsed = round_trip(&placed, AnsiColors::Ansi16, false);
+        assert_eq!(parsed[&(0, 0)], style::Color::Red);
+
+        let parsed = round_trip(&placed, AnsiColors::Ansi256, false);
+        assert_eq!(parsed[&(0, 0)], palette::color_256(196));
+    }
+
+    #[test]
+    fn half_blocks_are_chosen_not_guessed() {
+        let text = "\x1b[0;31;44m▀\x1b[0m\n";
+
+        let whole: Placed = parse_ansi(text.as_bytes(), false).into_iter().collect();
+        assert_eq!(whole, [((0, 0), style::Color::DarkRed)].into());
+
+        let halves: Placed = parse_ansi(text.as_bytes(), true).into_iter().collect();
+        assert_eq!(
+            halves,
+            [
+                ((0, 0), style::Color::DarkRed),
+                ((0, 1), style::Color::DarkBlue)
+            ]
+            .into()
+        );
+    }
+
+    #[test]
+    fn old_ansi_art() {
+        /* CP437 blocks, bold makes colors bright, cursor moves forward */
+        let bytes = b"\x1b[1;31m\xdb\x1b[2C\x1b[0;32m\xdb\x1a SAUCE";
+        let placed: Placed = parse_ansi(bytes, false).into_iter().collect();
+
+        assert_eq!(
+            placed,
+            [
+                ((0, 0), style::Color::Red),
+                ((3, 0), style::Color::DarkGreen)
+            ]
+            .into()
+        );
+    }
+
+    #[test]
+    fn blocks_past_largest_canvas_are_left_out() {
+        let bytes = b"\x1b[31m\xdb\x1b[4294967295C\xdb\xdb\r\x1b[65534C\xdb\xdb";
+
+        for half_blocks in [false, true] {
+            let blocks = parse_ansi(bytes, half_blocks);
+            let columns: Vec<u32> = blocks.iter().map(|(k, _)| k.0).collect();
+
+            assert_eq!(columns.len(), if half_blocks { 4 } else { 2 });
+            assert!(columns
+                .iter()
+                .all(|x| *x == 0 || *x == file::MAX_CANVAS - 1));
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use std::path::PathBuf;
+
+/// Usage printed by `gvpaint --help`
+pub const USAGE: &str = "\
+Usage:
+    gvpaint [OPTIONS] [FILE]            open FILE (.txt, .png or .ans) and start painting
+    gvpaint convert <INPUT> <OUTPUT>    convert between .txt, .png and .ans without opening canvas,
+                                        .gif and VGA buffers (.bin, .asm, .h) are written too
+    gvpaint check <FILE>                report problems in a text file as file:line: message
+
+Options:
+    --size WxH       canvas size, default is size of terminal or of opened file
+                     (check uses it to find blocks outside of canvas,
+                     convert uses it as VGA text mode, default is 80x25)
+    --history N      number of steps kept in undo history
+    -h, --help       print this help";
+
+/// What program should do, chosen by command line arguments
+pub enum Command {
+    Paint { file: Option<PathBuf> },
+    Convert { input: PathBuf, output: PathBuf },
+    Check { input: PathBuf },
+    Help,
+}
+
+/// Parsed command line arguments
+pub struct Arguments {
+    pub command: Command,
+    pub size: Option<(u16, u16)>,
+    pub history_depth: Option<usize>,
+}
+
+/// Parse `WxH` into width and height
+fn parse_size(size: &str) -> Result<(u16, u16), String> {
+    let invalid = || format!("invalid size `{}`, expected WxH, e.g. 80x24", size);
+
+    let (width, height) = size.split_once(['x', 'X']).ok_or_else(invalid)?;
+    let width: u16 = width.parse().map_err(|_| invalid())?;
+    let height: u16 = height.parse().map_err(|_| invalid())?;
+
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+
+    Ok((width, height))
+}
+
+/// Parse command line arguments, first one (program name) should be skipped already
+pub fn parse_args(args: impl Iterator<Item = String>) -> Result<Arguments, String> {
+    let mut size = None;
+    let mut history_depth = None;
+    let mut positional: Vec<String> = Vec::new();
+
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                return Ok(Arguments {
+                    command: Command::Help,
+                    size,
+                    history_depth,
+                })
+            }
+            "--size" => {
+                let value = args.next().ok_or("--size needs a value, e.g. 80x24")?;
+                size = Some(parse_size(&value)?);
+            }
+            "--history" => {
+                let value = args.next().ok_or("--history needs a number")?;
+                let depth = value
+                    .parse()
+                    .map_err(|_| format!("invalid history depth `{}`", value))?;
+                history_depth = Some(depth);
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option `{}`", arg)),
+            _ => positional.push(arg),
+        }
+    }
+
+    let command = match positional.first().map(String::as_str) {
+        Some("convert") => {
+            if positional.len() != 3 {
+                return Err("convert needs <INPUT> and <OUTPUT>".to_string());
+            }
+
+            Command::Convert {
+                input: PathBuf::from(&positional[1]),
+                output: PathBuf::from(&positional[2]),
+            }
+        }
+        Some("check") => {
+            if positional.len() != 2 {
+                return Err("check needs <FILE>".to_string());
+            }
+
+            Command::Check {
+                input: PathBuf::from(&positional[1]),
+            }
+        }
+        _ => {
+            if positional.len() > 1 {
+                return Err("only one file can be opened".to_string());
+            }
+
+            Command::Paint {
+                file: positional.first().map(PathBuf::from),
+            }
+        }
+    };
+
+    Ok(Arguments {
+        command,
+        size,
+        history_depth,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Arguments, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn no_arguments_start_empty_canvas() {
+        let arguments = parse(&[]).unwrap();
+
+        assert!(matches!(arguments.command, Command::Paint { file: None }));
+        assert_eq!(arguments.size, None);
+        assert_eq!(arguments.history_depth, None);
+    }
+
+    #[test]
+    fn file_and_options() {
+        let arguments = parse(&["--size", "120x40", "painting.txt", "--history", "500"]).unwrap();
+
+        match arguments.command {
+            Command::Paint { file } => assert_eq!(file, Some(PathBuf::from("painting.txt"))),
+            _ => panic!("expected paint command"),
+        }
+        assert_eq!(arguments.size, Some((120, 40)));
+        assert_eq!(arguments.history_depth, Some(500));
+    }
+
+    #[test]
+    fn convert_and_check() {
+        match parse(&["convert", "a.txt", "b.png"]).unwrap().command {
+            Command::Convert { input, output } => {
+                assert_eq!(input, PathBuf::from("a.txt"));
+                assert_eq!(output, PathBuf::from("b.png"));
+            }
+            _ => panic!("expected convert command"),
+        }
+        match parse(&["check", "a.txt"]).unwrap().command {
+            Command::Check { input } => assert_eq!(input, PathBuf::from("a.txt")),
+            _ => panic!("expected check command"),
+        }
+
+        assert!(parse(&["convert", "a.txt"]).is_err());
+        assert!(parse(&["check"]).is_err());
+    }
+
+    #[test]
+    fn help_wins_over_everything_else() {
+        let arguments = parse(&["convert", "-h"]).unwrap();
+        assert!(matches!(arguments.command, Command::Help));
+
+        let arguments = parse(&["--help", "--unknown"]).unwrap();
+        assert!(matches!(arguments.command, Command::Help));
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        assert!(parse(&["--size"]).is_err());
+        assert!(parse(&["--size", "80"]).is_err());
+        assert!(parse(&["--size", "0x24"]).is_err());
+        assert!(parse(&["--size", "80x70000"]).is_err());
+        assert!(parse(&["--history", "many"]).is_err());
+        assert!(parse(&["--colors"]).is_err());
+        assert!(parse(&["a.txt", "b.txt"]).is_err());
+    }
+
+    #[test]
+    fn size_accepts_both_separators() {
+        assert_eq!(parse_size("80x24"), Ok((80, 24)));
+        assert_eq!(parse_size("80X24"), Ok((80, 24)));
+    }
+}
@@ -0,0 +1,337 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crossterm::{event::KeyCode, style};
+
+use crate::ansi;
+use crate::file;
+use crate::render;
+use crate::variables;
+use crate::vga;
+use crate::viewport;
+use crate::window::{self, FileAction};
+
+/// Number of directory entries visible at once
+const VISIBLE_ENTRIES: usize = 10;
+
+/// Width of dialog window, border included
+const WIDTH: u16 = 62;
+
+/// Height of dialog window, border included
+const HEIGHT: u16 = 17;
+
+/// State of file dialog window
+pub struct FileDialog {
+    pub action: FileAction,
+    pub input: String,
+    pub entries: Vec<String>,
+    pub selected: usize,
+}
+
+impl FileDialog {
+    /// Extensions of files shown in listing, the first one is added when it's missing
+    fn extensions(&self) -> &'static [&'static str] {
+        match self.action {
+            FileAction::OpenText | FileAction::SaveText => &["txt"],
+            FileAction::OpenPng | FileAction::SavePng => &["png"],
+            FileAction::SaveAnimation => &["gif", "png"],
+            FileAction::OpenAnsi | FileAction::SaveAnsi => &[ansi::EXTENSION],
+            FileAction::SaveVga => vga::EXTENSIONS,
+            FileAction::OpenPalette | FileAction::SavePalette => file::PALETTE_EXTENSIONS,
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self.action {
+            FileAction::OpenText => "Open text file (*.txt)",
+            FileAction::SaveText => "Save as text file (*.txt)",
+            FileAction::OpenPng => "Open .png file (*.png)",
+            FileAction::SavePng => "Save as .png (*.png)",
+            FileAction::SaveAnimation => "Save animation (*.gif, *.png)",
+            FileAction::OpenAnsi => "Open ANSI art (*.ans)",
+            FileAction::SaveAnsi => "Save as ANSI art (*.ans)",
+            FileAction::SaveVga => "Save VGA text buffer (*.bin, *.asm, *.h)",
+            FileAction::OpenPalette => "Load palette (*.gpl, *.hex, *.pal)",
+            FileAction::SavePalette => "Save palette (*.gpl, *.hex, *.pal)",
+        }
+    }
+
+    /// Directory part of input, it's the one being listed
+    fn directory(&self) -> &str {
+        match self.input.rfind('/') {
+            Some(i) => &self.input[..=i],
+            None => "",
+        }
+    }
+
+    /// Re-read listing of current directory, only folders and files with matching extension
+    fn refresh(&mut self) {
+        let directory = match self.directory() {
+            "" => Path::new("."),
+            dir => Path::new(dir),
+        };
+
+        let mut folders: Vec<String> = vec!["../".to_string()];
+        let mut files: Vec<String> = Vec::new();
+
+        if let Ok(read_dir) = fs::read_dir(directory) {
+            for entry in read_dir.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if name.starts_with('.') {
+                    continue;
+                }
+
+                let path = entry.path();
+                if path.is_dir() {
+                    folders.push(name + "/");
+                } else if path.extension().is_some_and(|e| {
+                    self.extensions()
+                        .iter()
+                        .any(|extension| e.eq_ignore_ascii_case(extension))
+                }) {
+                    files.push(name);
+                }
+            }
+        }
+
+        folders.sort();
+        files.sort();
+        folders.append(&mut files);
+
+        self.entries = folders;
+        self.selected = self.selected.min(self.entries.len() - 1);
+    }
+
+    /// Put selected entry into input, folders are entered
+    fn complete(&mut self) {
+        let entry = self.entries[self.selected].clone();
+        let directory = self.directory().to_string();
+
+        if entry == "../" {
+            let path = Path::new(&directory);
+            self.input = match path.file_name() {
+                Some(_) => {
+                    let parent = path.parent().unwrap_or(Path::new("")).to_string_lossy();
+                    if parent.is_empty() || parent.ends_with('/') {
+                        parent.to_string()
+                    } else {
+                        parent.to_string() + "/"
+                    }
+                }
+                None => directory + "../",
+            };
+        } else {
+            self.input = directory + &entry;
+        }
+
+        if entry.ends_with('/') {
+            self.selected = 0;
+            self.refresh();
+        }
+    }
+
+    /// Path which should be used for action, extension is added if it's missing
+    fn path(&self) -> PathBuf {
+        let mut path = PathBuf::from(&self.input);
+        if path.extension().is_none() {
+            path.set_extension(self.extensions()[0]);
+        }
+
+        path
+    }
+}
+
+/// Position of dialog's top left corner
+fn origin(viewport: &viewport::Viewport) -> (u16, u16) {
+    (4, viewport.height.saturating_sub(HEIGHT + 2))
+}
+
+/// Open a file dialog for given file window action
+pub fn open_dialog(
+    state: &mut variables::State,
+    file_menu: &mut variables::FileMenu,
+    action: FileAction,
+) {
+    let mut dialog = FileDialog {
+        action,
+        input: String::new(),
+        entries: Vec::new(),
+        selected: 0,
+    };
+
+    /* start from a file used last time in this session, but with right extension */
+    let last_path = file_menu
+        .last_path
+        .clone()
+        .unwrap_or(PathBuf::from("painting.txt"));
+    let extension = last_path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .filter(|e| dialog.extensions().contains(&e.as_str()))
+        .unwrap_or(dialog.extensions()[0].to_string());
+    dialog.input = last_path
+        .with_extension(extension)
+        .to_string_lossy()
+        .to_string();
+    dialog.refresh();
+
+    file_menu.dialog = Some(dialog);
+    state.window_open = true;
+    state.window_open_name = "dialog".to_string();
+}
+
+/// Close dialog, canvas under it is shown again
+fn close_dialog(state: &mut variables::State, file_menu: &mut variables::FileMenu) {
+    file_menu.dialog = None;
+    state.window_open = false;
+}
+
+/// Draw a dialog window over canvas
+pub fn draw_dialog(
+    renderer: &mut render::Renderer,
+    viewport: &viewport::Viewport,
+    file_menu: &variables::FileMenu,
+) {
+    let Some(dialog) = file_menu.dialog.as_ref() else {
+        return;
+    };
+
+    let (x, y) = origin(viewport);
+    window::draw_border(renderer, x, y, WIDTH, HEIGHT);
+
+    /* show the end of a long path, that's where user types */
+    let room = WIDTH as usize - 10;
+    let chars: Vec<char> = dialog.input.chars().collect();
+    let visible: String = chars[chars.len().saturating_sub(room)..].iter().collect();
+
+    window::print_lines(
+        renderer,
+        x + 2,
+        y + 1,
+        &[dialog.title(), &format!("Path: {}_", visible)],
+        style::Color::Red,
+    );
+
+    let scroll = (dialog.selected + 1).saturating_sub(VISIBLE_ENTRIES);
+    for (i, entry) in dialog
+        .entries
+        .iter()
+        .enumerate()
+        .skip(scroll)
+        .take(VISIBLE_ENTRIES)
+    {
+        let (line, color) = if i == dialog.selected {
+            (format!("> {}", entry), style::Color::DarkYellow)
+        } else {
+            (format!("  {}", entry), style::Color::Red)
+        };
+
+        let line: String = line.chars().take(WIDTH as usize - 4).collect();
+        window::print_lines(renderer, x + 2, y + 4 + (i - scroll) as u16, &[&line], color);
+    }
+
+    window::print_lines(
+        renderer,
+        x + 2,
+        y + HEIGHT - 2,
+        &["Enter - confirm, Tab - pick entry, Esc - cancel"],
+        style::Color::DarkGreen,
+    );
+}
+
+/// Confirm typed path, folders are entered and files are opened or saved
+fn confirm(
+    canvas: &mut variables::Canvas,
+    runtime: &mut variables::Runtime,
+    state: &mut variables::State,
+    file_menu: &mut variables::FileMenu,
+) {
+    let Some(dialog) = file_menu.dialog.as_mut() else {
+        return;
+    };
+
+    if dialog.input.is_empty() || dialog.input.ends_with('/') {
+        dialog.complete();
+        return;
+    }
+
+    if Path::new(&dialog.input).is_dir() {
+        dialog.input.push('/');
+        dialog.selected = 0;
+        dialog.refresh();
+        return;
+    }
+
+    let action = dialog.action;
+    let path = dialog.path();
+
+    close_dialog(state, file_menu);
+    file_menu.last_path = Some(path.clone());
+
+    window::file_window_actions(canvas, runtime, state, file_menu, action, &path);
+}
+
+/// Handle a key pressed while dialog is open
+pub fn dialog_input(
+    canvas: &mut variables::Canvas,
+    runtime: &mut variables::Runtime,
+    state: &mut variables::State,
+    file_menu: &mut variables::FileMenu,
+    code: KeyCode,
+) {
+    let Some(dialog) = file_menu.dialog.as_mut() else {
+        return;
+    };
+
+    match code {
+        KeyCode::Esc => {
+            close_dialog(state, file_menu);
+        }
+        KeyCode::Enter => {
+            confirm(canvas, runtime, state, file_menu);
+        }
+        KeyCode::Tab => dialog.complete(),
+        KeyCode::Up => dialog.selected = dialog.selected.saturating_sub(1),
+        KeyCode::Down => dialog.selected = (dialog.selected + 1).min(dialog.entries.len() - 1),
+        KeyCode::Backspace => {
+            dialog.input.pop();
+            dialog.refresh();
+        }
+        KeyCode::Char(c) => {
+            dialog.input.push(c);
+            if c == '/' {
+                dialog.selected = 0;
+                dialog.refresh();
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Handle a mouse click while dialog is open, clicked entry is picked
+pub fn dialog_click(
+    viewport: &viewport::Viewport,
+    file_menu: &mut variables::FileMenu,
+    column: u16,
+    row: u16,
+) {
+    let (x, y) = origin(viewport);
+    let Some(dialog) = file_menu.dialog.as_mut() else {
+        return;
+    };
+
+    if column <= x || column >= x + WIDTH - 1 || row < y + 4 {
+        return;
+    }
+
+    let scroll = (dialog.selected + 1).saturating_sub(VISIBLE_ENTRIES);
+    let i = scroll + (row - y - 4) as usize;
+    if (row - y - 4) as usize >= VISIBLE_ENTRIES || i >= dialog.entries.len() {
+        return;
+    }
+
+    dialog.selected = i;
+    dialog.complete();
+}
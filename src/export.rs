@@ -0,0 +1,275 @@
+use std::path::{Path, PathBuf};
+
+use crossterm::{event::KeyCode, style};
+
+use crate::file;
+use crate::render;
+use crate::variables;
+use crate::viewport;
+use crate::window;
+
+/// Width of export window, border included
+const WIDTH: u16 = 49;
+
+/// Height of export window, border included
+const HEIGHT: u16 = 10;
+
+/// Animations have two more options
+const ANIMATION_HEIGHT: u16 = 12;
+
+/// Scale factors export window cycles through
+const SCALES: [u32; 8] = [1, 2, 3, 4, 6, 8, 12, 16];
+
+/// Frame delays in milliseconds export window cycles through, `None` follows timeline's FPS
+const DELAYS: [Option<u32>; 8] = [
+    None,
+    Some(20),
+    Some(50),
+    Some(100),
+    Some(200),
+    Some(500),
+    Some(1000),
+    Some(2000),
+];
+
+/// Loop counts export window cycles through, 0 plays animation forever
+const LOOPS: [u32; 6] = [0, 1, 2, 3, 5, 10];
+
+/// Options of .png export, they stay the same until GVPaint is closed
+pub struct ExportOptions {
+    /// Every block is `scale` x `scale` pixels
+    pub scale: u32,
+    /// Empty cells are transparent instead of white
+    pub transparent: bool,
+    /// Export only the bounding box of placed blocks
+    pub crop: bool,
+    /// Blocks are twice as tall as wide, like terminal cells
+    pub aspect: bool,
+    /// How long every frame of animation is shown in milliseconds, `None` follows timeline's FPS
+    pub delay: Option<u32>,
+    /// How many times animation is played, 0 is forever
+    pub loops: u32,
+}
+
+/// Ask how a painting should be exported before .png file is saved.
+/// With `frames` every frame is saved to an animated .gif or .png
+pub fn export_window(
+    state: &mut variables::State,
+    file_menu: &mut variables::FileMenu,
+    path: &Path,
+    frames: bool,
+) {
+    file_menu.export = Some(PathBuf::from(path));
+    file_menu.export_frames = frames;
+    state.window_open = true;
+    state.window_open_name = "export".to_string();
+}
+
+/// Height of window, border included
+fn height(file_menu: &variables::FileMenu) -> u16 {
+    if file_menu.export_frames {
+        ANIMATION_HEIGHT
+    } else {
+        HEIGHT
+    }
+}
+
+/// Position of window's top left corner
+fn origin(viewport: &viewport::Viewport, file_menu: &variables::FileMenu) -> (u16, u16) {
+    (
+        4,
+        viewport.height.saturating_sub(height(file_menu) + 2).max(1),
+    )
+}
+
+/// Milliseconds every frame is shown
+fn frame_delay(options: &ExportOptions, fps: u32) -> u32 {
+    options.delay.unwrap_or(1000 / fps.max(1))
+}
+
+/// Draw export window with current options
+pub fn draw_export_window(
+    renderer: &mut render::Renderer,
+    viewport: &viewport::Viewport,
+    runtime: &variables::Runtime,
+    file_menu: &variables::FileMenu,
+) {
+    let Some(path) = file_menu.export.as_ref() else {
+        return;
+    };
+
+    let (x, y) = origin(viewport, file_menu);
+    let height = height(file_menu);
+    let options = &file_menu.export_options;
+    window::draw_border(renderer, x, y, WIDTH, height);
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let title = if file_menu.export_frames {
+        format!("Save {} frames: {}", runtime.layers.frames.len(), name)
+    } else {
+        format!("Save .png file: {}", name)
+    };
+    let title: String = title.chars().take(WIDTH as usize - 4).collect();
+    window::print_lines(renderer, x + 2, y + 1, &[&title], style::Color::Red);
+
+    let yes_no = |value: bool| if value { "yes" } else { "no" };
+    let scale = format!(
+        "1 - scale: {}x{} pixels per block",
+        options.scale,
+        if options.aspect {
+            options.scale * 2
+        } else {
+            options.scale
+        }
+    );
+    let background = if options.transparent {
+        "2 - empty cells: transparent"
+    } else {
+        "2 - empty cells: white"
+    };
+    let crop = format!("3 - crop to painted blocks: {}", yes_no(options.crop));
+    let aspect = format!(
+        "4 - blocks as tall as cells (1:2): {}",
+        yes_no(options.aspect)
+    );
+
+    window::print_lines(
+        renderer,
+        x + 2,
+        y + 3,
+        &[&scale, background, &crop, &aspect],
+        style::Color::DarkYellow,
+    );
+
+    let mut hint = "1 - 4 - change, Enter - save, Esc - cancel";
+    if file_menu.export_frames {
+        let delay = frame_delay(options, runtime.layers.fps);
+        let delay = match options.delay {
+            None => format!("5 - frame delay: {} ms ({} FPS)", delay, runtime.layers.fps),
+            Some(_) => format!("5 - frame delay: {} ms", delay),
+        };
+        let loops = match options.loops {
+            0 => "6 - play: forever".to_string(),
+            1 => "6 - play: once".to_string(),
+            n => format!("6 - play: {} times", n),
+        };
+
+        window::print_lines(
+            renderer,
+            x + 2,
+            y + 7,
+            &[&delay, &loops],
+            style::Color::DarkYellow,
+        );
+        hint = "1 - 6 - change, Enter - save, Esc - cancel";
+    }
+
+    window::print_lines(
+        renderer,
+        x + 2,
+        y + height - 2,
+        &[hint],
+        style::Color::DarkGreen,
+    );
+}
+
+/// Change an option, 0 is the first line of options
+fn toggle_option(options: &mut ExportOptions, line: u16) {
+    match line {
+        0 => {
+            let i = SCALES.iter().position(|s| *s == options.scale).unwrap_or(0);
+            options.scale = SCALES[(i + 1) % SCALES.len()];
+        }
+        1 => options.transparent = !options.transparent,
+        2 => options.crop = !options.crop,
+        3 => options.aspect = !options.aspect,
+        4 => {
+            let i = DELAYS.iter().position(|d| *d == options.delay).unwrap_or(0);
+            options.delay = DELAYS[(i + 1) % DELAYS.len()];
+        }
+        5 => {
+            let i = LOOPS.iter().position(|l| *l == options.loops).unwrap_or(0);
+            options.loops = LOOPS[(i + 1) % LOOPS.len()];
+        }
+        _ => {}
+    }
+}
+
+/// Save painting to .png file waiting in export window, or all of its frames to an animation
+fn save(
+    canvas: &variables::Canvas,
+    runtime: &variables::Runtime,
+    state: &mut variables::State,
+    file_menu: &mut variables::FileMenu,
+) {
+    state.window_open = false;
+    let Some(path) = file_menu.export.take() else {
+        return;
+    };
+
+    let options = &file_menu.export_options;
+    let result = if file_menu.export_frames {
+        file::save_animation(
+            &path,
+            &runtime.layers.flatten_frames(),
+            (canvas.width as u32, canvas.height as u32),
+            options,
+            frame_delay(options, runtime.layers.fps),
+            options.loops,
+        )
+    } else {
+        file::save_png(
+            &path,
+            &runtime.layers.flatten(runtime.layers.frame),
+            canvas.width as u32,
+            canvas.height as u32,
+            options,
+        )
+    };
+
+    if let Err(e) = result {
+        window::message_box(state, "Error", &e.to_string());
+    }
+}
+
+/// Handle a key pressed while export window is open
+pub fn export_input(
+    canvas: &variables::Canvas,
+    runtime: &variables::Runtime,
+    state: &mut variables::State,
+    file_menu: &mut variables::FileMenu,
+    code: KeyCode,
+) {
+    match code {
+        KeyCode::Esc => {
+            file_menu.export = None;
+            state.window_open = false;
+        }
+        KeyCode::Enter => save(canvas, runtime, state, file_menu),
+        KeyCode::Char(c @ '1'..='4') => {
+            toggle_option(&mut file_menu.export_options, c as u16 - '1' as u16)
+        }
+        KeyCode::Char(c @ '5'..='6') if file_menu.export_frames => {
+            toggle_option(&mut file_menu.export_options, c as u16 - '1' as u16)
+        }
+        _ => {}
+    }
+}
+
+/// Handle a mouse click while export window is open, clicked option is changed
+pub fn export_click(
+    viewport: &viewport::Viewport,
+    file_menu: &mut variables::FileMenu,
+    column: u16,
+    row: u16,
+) {
+    let (x, y) = origin(viewport, file_menu);
+    if column <= x || column >= x + WIDTH - 1 || row < y + 3 {
+        return;
+    }
+
+    let line = row - y - 3;
+    if line < 4 || file_menu.export_frames {
+        toggle_option(&mut file_menu.export_options, line);
+    }
+}
@@ -0,0 +1,1159 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crossterm::style;
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    open, Delay, Frame, Pixel, Rgb, Rgba, RgbaImage,
+};
+
+use crate::ansi;
+use crate::export::ExportOptions;
+use crate::paint;
+use crate::palette;
+use crate::vga;
+
+/// Blocks read from a file, position and color
+pub type Blocks = Vec<((u32, u32), style::Color)>;
+
+/// Problem found on one line of a text file, lines are counted from 1
+pub struct LineError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// Errors which can happen while opening or saving paintings
+pub enum FileError {
+    /// File couldn't be read
+    Read(PathBuf, io::Error),
+    /// File couldn't be written
+    Write(PathBuf, io::Error),
+    /// Old file couldn't be renamed to a backup
+    Backup(PathBuf, io::Error),
+    /// Image couldn't be decoded or encoded
+    Image(PathBuf, image::ImageError),
+    /// Animated .png couldn't be encoded
+    Apng(PathBuf, png::EncodingError),
+    /// Text file doesn't follow the format from GUIDE.md
+    Parse(PathBuf, Vec<LineError>),
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileError::Read(path, e) => write!(f, "Couldn't open `{}`: {}", path.display(), e),
+            FileError::Write(path, e) => write!(f, "Couldn't save `{}`: {}", path.display(), e),
+            FileError::Backup(path, e) => {
+                write!(f, "Couldn't make a backup of `{}`: {}", path.display(), e)
+            }
+            FileError::Image(path, e) => write!(f, "Image `{}`: {}", path.display(), e),
+            FileError::Apng(path, e) => write!(f, "Image `{}`: {}", path.display(), e),
+            FileError::Parse(path, errors) => {
+                let lines: Vec<String> = errors
+                    .iter()
+                    .map(|e| format!("{}:{}: {}", path.display(), e.line, e.message))
+                    .collect();
+
+                write!(f, "{}", lines.join("\n"))
+            }
+        }
+    }
+}
+
+/// Newest version of text files, files without header are read as legacy format
+pub const TEXT_VERSION: u32 = 4;
+
+/// Version of files with a single frame, older GVPaint can read them too
+const SINGLE_FRAME_VERSION: u32 = 3;
+
+/// Canvas can't be wider or taller than this, blocks further away are never shown
+pub const MAX_CANVAS: u32 = u16::MAX as u32;
+
+/// Frames per second of animation when file doesn't say
+pub const DEFAULT_FPS: u32 = 8;
+
+/// Painting read from a file: blocks of every frame of animation and its speed
+pub struct Document {
+    pub frames: Vec<Blocks>,
+    pub fps: u32,
+}
+
+impl Document {
+    /// Blocks of the first frame, used when only a single picture is needed
+    pub fn first_frame(self) -> Blocks {
+        self.frames.into_iter().next().unwrap_or_default()
+    }
+
+    /// Size of the smallest canvas which holds blocks of every frame
+    pub fn extent(&self) -> (u32, u32) {
+        self.frames
+            .iter()
+            .map(extent)
+            .fold((1, 1), |(w, h), (fw, fh)| (w.max(fw), h.max(fh)))
+    }
+}
+
+/// Parse `#rrggbb`
+fn parse_hex(text: &str) -> Option<style::Color> {
+    let hex = text
+        .strip_prefix('#')
+        .filter(|hex| hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()))?;
+    let value = u32::from_str_radix(hex, 16).ok()?;
+
+    Some(style::Color::Rgb {
+        r: (value >> 16) as u8,
+        g: (value >> 8) as u8,
+        b: value as u8,
+    })
+}
+
+/// Write a color as palette entry: `ansi N` for terminal's named colors, `#rrggbb` otherwise
+fn color_to_text(color: style::Color) -> String {
+    if let Some(index) = (0..16).find(|i| palette::ansi_color(*i) == color) {
+        return format!("ansi {}", index);
+    }
+
+    let rgb = crossterm_to_image_color(color);
+
+    format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2])
+}
+
+/// Parse one entry of embedded palette: `ansi N` or `#rrggbb`
+fn parse_palette_entry(line: &str) -> Result<style::Color, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+
+    match fields[..] {
+        ["ansi", index] => index
+            .parse::<u8>()
+            .ok()
+            .filter(|index| *index < 16)
+            .map(palette::ansi_color)
+            .ok_or(format!("ANSI color `{}` is not a number 0 - 15", index)),
+        [hex] => parse_hex(hex).ok_or(format!("palette color `{}` is not #rrggbb", hex)),
+        _ => Err(format!("expected palette color (#rrggbb or ansi N), found `{}`", line.trim())),
+    }
+}
+
+/// Parse `GVPAINT N` header, only versions 3 and 4 have one (legacy files have no header)
+fn parse_header(line: &str) -> Result<(), String> {
+    let version = line.split_whitespace().nth(1).unwrap_or_default();
+
+    match version.parse::<u32>() {
+        Ok(SINGLE_FRAME_VERSION | TEXT_VERSION) => Ok(()),
+        Ok(version) => Err(format!(
+            "unsupported version {}, gvpaint reads versions {} and {}",
+            version, SINGLE_FRAME_VERSION, TEXT_VERSION
+        )),
+        Err(_) => Err(format!("expected `GVPAINT {}` header", TEXT_VERSION)),
+    }
+}
+
+/// Parse one line with a block: `x y color`. Legacy files (no embedded palette) use colors 10 - 19,
+/// newer ones `#rrggbb` or an index to embedded palette
+fn parse_line(
+    line: &str,
+    size: Option<(u32, u32)>,
+    colors: Option<&[style::Color]>,
+) -> Result<((u32, u32), style::Color), String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() != 3 {
+        return Err(format!(
+            "expected 3 numbers (x, y and color), found {}",
+            fields.len()
+        ));
+    }
+
+    let number = |field: &str, name: &str| -> Result<u32, String> {
+        field
+            .parse()
+            .map_err(|_| format!("{} `{}` is not a number", name, field))
+    };
+
+    let x = number(fields[0], "x")?;
+    let y = number(fields[1], "y")?;
+
+    match size {
+        Some((width, height)) if x >= width || y >= height => {
+            return Err(format!(
+                "block {} {} is outside of canvas {}x{}",
+                x, y, width, height
+            ));
+        }
+        None if x >= MAX_CANVAS || y >= MAX_CANVAS => {
+            return Err(format!(
+                "block {} {} is outside of the largest canvas {}x{}",
+                x, y, MAX_CANVAS, MAX_CANVAS
+            ));
+        }
+        _ => {}
+    }
+
+    let color = match colors {
+        None => {
+            let color_number = number(fields[2], "color")?;
+            paint::color_from_number(color_number).ok_or(format!(
+                "color {} is not in palette, use {} - {}",
+                color_number,
+                paint::KEY::C1 as u32,
+                paint::KEY::C0 as u32
+            ))?
+        }
+        Some(_) if fields[2].starts_with('#') => parse_hex(fields[2])
+            .ok_or(format!("color `{}` is not #rrggbb", fields[2]))?,
+        Some(colors) => {
+            let index = number(fields[2], "color")?;
+            *colors.get(index as usize).ok_or(format!(
+                "color {} is not in palette, it has {} colors",
+                index,
+                colors.len()
+            ))?
+        }
+    };
+
+    Ok(((x, y), color))
+}
+
+/// Parse content of a text file, format is described in GUIDE.md.
+/// Blocks outside of canvas of given size, or of the largest one, are reported too
+pub fn parse_text(content: &str, size: Option<(u32, u32)>) -> Result<Document, Vec<LineError>> {
+    let mut frames: Vec<Blocks> = vec![Vec::new()];
+    let mut fps = DEFAULT_FPS;
+    let mut errors: Vec<LineError> = Vec::new();
+
+    /* legacy files have no header and no palette, `colors` stays None for them */
+    let mut colors: Option<Vec<style::Color>> = None;
+    let mut palette_left = 0;
+    let mut first = true;
+
+    for (i, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let result = if first && line.starts_with("GVPAINT") {
+            colors = Some(Vec::new());
+            parse_header(line)
+        } else if palette_left > 0 {
+            palette_left -= 1;
+            parse_palette_entry(line).map(|color| colors.iter_mut().for_each(|c| c.push(color)))
+        } else if let (Some(_), Some(count)) = (&colors, line.strip_prefix("palette ")) {
+            count
+                .trim()
+                .parse()
+                .map(|count| palette_left = count)
+                .map_err(|_| format!("palette size `{}` is not a number", count.trim()))
+        } else if colors.is_some() && line.trim() == "frame" {
+            frames.push(Vec::new());
+            Ok(())
+        } else if let (Some(_), Some(value)) = (&colors, line.strip_prefix("fps ")) {
+            value
+                .trim()
+                .parse()
+                .ok()
+                .filter(|value| *value > 0)
+                .map(|value| fps = value)
+                .ok_or(format!("fps `{}` is not a number above 0", value.trim()))
+        } else {
+            let blocks = frames.last_mut().expect("there is always a frame");
+            parse_line(line, size, colors.as_deref()).map(|block| blocks.push(block))
+        };
+        first = false;
+
+        if let Err(message) = result {
+            errors.push(LineError {
+                line: i + 1,
+                message,
+            });
+        }
+    }
+
+    if palette_left > 0 {
+        errors.push(LineError {
+            line: content.lines().count(),
+            message: format!("palette is missing {} colors", palette_left),
+        });
+    }
+
+    if errors.is_empty() {
+        Ok(Document { frames, fps })
+    } else {
+        Err(errors)
+    }
+}
+
+/// Convert frames of placed blocks to text format: header, palette of used colors and blocks
+/// with an index to it. Frames after the first one start with `frame` line
+pub fn painting_to_text(frames: &[BTreeMap<(u32, u32), style::Color>], fps: u32) -> String {
+    let mut colors: Vec<style::Color> = Vec::new();
+    let mut indices: HashMap<style::Color, usize> = HashMap::new();
+    for color in frames.iter().flat_map(|placed| placed.values()) {
+        indices.entry(*color).or_insert_with(|| {
+            colors.push(*color);
+            colors.len() - 1
+        });
+    }
+
+    let mut string = if frames.len() > 1 {
+        format!("GVPAINT {}\nfps {}\n", TEXT_VERSION, fps)
+    } else {
+        format!("GVPAINT {}\n", SINGLE_FRAME_VERSION)
+    };
+
+    string += &format!("palette {}\n", colors.len());
+    for color in &colors {
+        string += &color_to_text(*color);
+        string.push('\n');
+    }
+
+    for (i, placed) in frames.iter().enumerate() {
+        if i > 0 {
+            string += "frame\n";
+        }
+        for (k, v) in placed {
+            string += &format!("{} {} {}\n", k.0, k.1, indices[v]);
+        }
+    }
+
+    string
+}
+
+/// Read a painting from text file, blocks are checked against canvas size if it's given
+pub fn read_text(path: &Path, size: Option<(u32, u32)>) -> Result<Document, FileError> {
+    let content = fs::read_to_string(path).map_err(|e| FileError::Read(path.into(), e))?;
+    parse_text(&content, size).map_err(|e| FileError::Parse(path.into(), e))
+}
+
+/// Name of backup of a file, `name.txt` becomes `name2.txt`
+fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.file_stem().unwrap_or_default().to_os_string();
+    backup.push("2");
+    if let Some(extension) = path.extension() {
+        backup.push(".");
+        backup.push(extension);
+    }
+
+    path.with_file_name(backup)
+}
+
+/// Save frames of a painting to text file. With `backup` old file is kept
+/// (`name.txt` becomes `name2.txt`), otherwise it's overwritten
+pub fn save_text(
+    path: &Path,
+    frames: &[BTreeMap<(u32, u32), style::Color>],
+    fps: u32,
+    backup: bool,
+) -> Result<(), FileError> {
+    if backup && path.exists() {
+        fs::rename(path, backup_path(path)).map_err(|e| FileError::Backup(path.into(), e))?;
+    }
+
+    fs::write(path, painting_to_text(frames, fps)).map_err(|e| FileError::Write(path.into(), e))
+}
+
+/// Pixels of .png files with less alpha are left out
+pub const OPAQUE: u8 = 128;
+
+/// Read .png file as RGBA image
+pub fn read_rgba(path: &Path) -> Result<RgbaImage, FileError> {
+    Ok(open(path)
+        .map_err(|e| FileError::Image(path.into(), e))?
+        .to_rgba8())
+}
+
+/// Blocks of every pixel of an image, transparent pixels stay empty
+pub fn image_to_blocks(img: &RgbaImage) -> Blocks {
+    img.enumerate_pixels()
+        .filter(|(_, _, pixel)| pixel[3] >= OPAQUE)
+        .map(|(x, y, pixel)| ((x, y), rgb_to_crossterm_color(pixel.to_rgb())))
+        .collect()
+}
+
+/// Read every pixel of .png file, transparent pixels stay empty
+pub fn read_png(path: &Path) -> Result<Blocks, FileError> {
+    Ok(image_to_blocks(&read_rgba(path)?))
+}
+
+/// Part of canvas which is exported, top left corner and size. Cropped area is
+/// the bounding box of placed blocks, empty painting is cropped to a single cell
+fn export_area(
+    placed: &BTreeMap<(u32, u32), style::Color>,
+    width: u32,
+    height: u32,
+    crop: bool,
+) -> ((u32, u32), (u32, u32)) {
+    if !crop {
+        return ((0, 0), (width, height));
+    }
+
+    let inside: Vec<&(u32, u32)> = placed
+        .keys()
+        .filter(|k| k.0 < width && k.1 < height)
+        .collect();
+    if inside.is_empty() {
+        return ((0, 0), (1, 1));
+    }
+
+    let from = inside
+        .iter()
+        .fold((u32::MAX, u32::MAX), |(x, y), k| (x.min(k.0), y.min(k.1)));
+    let to = inside
+        .iter()
+        .fold((0, 0), |(x, y), k| (x.max(k.0), y.max(k.1)));
+
+    (from, (to.0 - from.0 + 1, to.1 - from.1 + 1))
+}
+
+/// Draw blocks inside of exported area to an image.
+/// Every block is `scale` pixels wide and as tall, or twice as tall with aspect correction.
+/// Empty cells are white or transparent
+fn draw_area(
+    placed: &BTreeMap<(u32, u32), style::Color>,
+    (from, (area_width, area_height)): ((u32, u32), (u32, u32)),
+    options: &ExportOptions,
+) -> RgbaImage {
+    let block_width = options.scale;
+    let block_height = if options.aspect {
+        options.scale * 2
+    } else {
+        options.scale
+    };
+
+    let background = if options.transparent {
+        Rgba([0, 0, 0, 0])
+    } else {
+        Rgba([255, 255, 255, 255])
+    };
+    let mut img = RgbaImage::from_pixel(
+        area_width * block_width,
+        area_height * block_height,
+        background,
+    );
+
+    for (k, v) in placed.range((from.0, 0)..(from.0 + area_width, 0)) {
+        if k.1 < from.1 || k.1 >= from.1 + area_height {
+            continue;
+        }
+
+        let color = crossterm_to_image_color(*v).to_rgba();
+        let x = (k.0 - from.0) * block_width;
+        let y = (k.1 - from.1) * block_height;
+
+        for dy in 0..block_height {
+            for dx in 0..block_width {
+                img.put_pixel(x + dx, y + dy, color);
+            }
+        }
+    }
+
+    img
+}
+
+/// Draw a painting to an image, canvas is of given size and blocks outside of it are skipped
+pub fn painting_to_image(
+    placed: &BTreeMap<(u32, u32), style::Color>,
+    width: u32,
+    height: u32,
+    options: &ExportOptions,
+) -> RgbaImage {
+    let area = export_area(placed, width, height, options.crop);
+    draw_area(placed, area, options)
+}
+
+/// Draw every frame of an animation to an image. Cropped frames share the bounding box
+/// of all frames, so they stay lined up
+pub fn animation_to_images(
+    frames: &[BTreeMap<(u32, u32), style::Color>],
+    width: u32,
+    height: u32,
+    options: &ExportOptions,
+) -> Vec<RgbaImage> {
+    let all: BTreeMap<(u32, u32), style::Color> = frames
+        .iter()
+        .flat_map(|frame| frame.iter().map(|(k, v)| (*k, *v)))
+        .collect();
+    let area = export_area(&all, width, height, options.crop);
+
+    frames
+        .iter()
+        .map(|frame| draw_area(frame, area, options))
+        .collect()
+}
+
+/// Save a painting to .png file, canvas is of given size
+pub fn save_png(
+    path: &Path,
+    placed: &BTreeMap<(u32, u32), style::Color>,
+    width: u32,
+    height: u32,
+    options: &ExportOptions,
+) -> Result<(), FileError> {
+    painting_to_image(placed, width, height, options)
+        .save(path)
+        .map_err(|e| FileError::Image(path.into(), e))
+}
+
+/// Save every frame to animated .gif, or animated .png for any other extension.
+/// Frames are shown for `delay` milliseconds, animation is played `loops` times or forever for 0
+pub fn save_animation(
+    path: &Path,
+    frames: &[BTreeMap<(u32, u32), style::Color>],
+    (width, height): (u32, u32),
+    options: &ExportOptions,
+    delay: u32,
+    loops: u32,
+) -> Result<(), FileError> {
+    let images = animation_to_images(frames, width, height, options);
+    let file = fs::File::create(path).map_err(|e| FileError::Write(path.into(), e))?;
+    let writer = io::BufWriter::new(file);
+
+    if is_gif(path) {
+        save_gif(writer, images, delay, loops).map_err(|e| FileError::Image(path.into(), e))
+    } else {
+        save_apng(writer, &images, delay, loops).map_err(|e| FileError::Apng(path.into(), e))
+    }
+}
+
+/// Encode frames as .gif, its loop count says how many times animation is repeated
+fn save_gif(
+    writer: impl io::Write,
+    images: Vec<RgbaImage>,
+    delay: u32,
+    loops: u32,
+) -> Result<(), image::ImageError> {
+    let mut encoder = GifEncoder::new(writer);
+    encoder.set_repeat(match loops {
+        0 => Repeat::Infinite,
+        n => Repeat::Finite(n.saturating_sub(1).min(u16::MAX as u32) as u16),
+    })?;
+
+    let delay = Delay::from_numer_denom_ms(delay, 1);
+    encoder.encode_frames(
+        images
+            .into_iter()
+            .map(|img| Frame::from_parts(img, 0, 0, delay)),
+    )
+}
+
+/// Encode frames as animated .png, its play count is the number of loops
+fn save_apng(
+    writer: impl io::Write,
+    images: &[RgbaImage],
+    delay: u32,
+    loops: u32,
+) -> Result<(), png::EncodingError> {
+    let Some(first) = images.first() else {
+        return Ok(());
+    };
+
+    let mut encoder = png::Encoder::new(writer, first.width(), first.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(images.len() as u32, loops)?;
+    encoder.set_frame_delay(delay.min(u16::MAX as u32) as u16, 1000)?;
+
+    let mut writer = encoder.write_header()?;
+    for img in images {
+        writer.write_image_data(img.as_raw())?;
+    }
+    writer.finish()
+}
+
+/// Read .png, ANSI art or text file, chosen by extension
+pub fn read_painting(path: &Path, size: Option<(u32, u32)>) -> Result<Document, FileError> {
+    if is_png(path) {
+        Ok(Document {
+            frames: vec![read_png(path)?],
+            fps: DEFAULT_FPS,
+        })
+    } else if ansi::is_ansi(path) {
+        Ok(Document {
+            frames: vec![ansi::read_ansi(path, false)?],
+            fps: DEFAULT_FPS,
+        })
+    } else {
+        read_text(path, size)
+    }
+}
+
+/// Size of the smallest canvas which holds all blocks, at least 1x1
+pub fn extent(blocks: &Blocks) -> (u32, u32) {
+    blocks
+        .iter()
+        .fold((1, 1), |(w, h), (k, _)| {
+            (w.max(k.0.saturating_add(1)), h.max(k.1.saturating_add(1)))
+        })
+}
+
+/// Check if a file should be treated as .png
+pub fn is_png(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("png"))
+}
+
+/// Check if a file should be treated as .gif
+pub fn is_gif(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("gif"))
+}
+
+/// Convert a file without opening the canvas, used by `gvpaint convert`.
+/// If size is not given, .png is as large as the painting and VGA buffer is 80x25.
+/// Only the first frame of an animation is saved to .png, ANSI art and VGA buffers,
+/// .gif gets all of them
+pub fn convert(input: &Path, output: &Path, size: Option<(u16, u16)>) -> Result<(), FileError> {
+    let size_u32 = size.map(|(w, h)| (w as u32, h as u32));
+    let document = read_painting(input, size_u32)?;
+    let options = ExportOptions {
+        scale: 1,
+        transparent: false,
+        crop: false,
+        aspect: false,
+        delay: None,
+        loops: 0,
+    };
+
+    if is_gif(output) {
+        let size = size_u32.unwrap_or_else(|| document.extent());
+        let frames: Vec<BTreeMap<(u32, u32), style::Color>> = document
+            .frames
+            .into_iter()
+            .map(|blocks| blocks.into_iter().collect())
+            .collect();
+
+        save_animation(output, &frames, size, &options, 1000 / document.fps.max(1), 0)
+    } else if vga::vga_format(output).is_some() {
+        let placed: BTreeMap<(u32, u32), style::Color> =
+            document.first_frame().into_iter().collect();
+        let options = vga::VgaOptions {
+            size: size_u32.unwrap_or((80, 25)),
+            half_blocks: false,
+            bright_background: false,
+        };
+
+        vga::save_vga(output, &placed, &options)
+    } else if is_png(output) || ansi::is_ansi(output) {
+        let blocks = document.first_frame();
+        let (width, height) = size_u32.unwrap_or_else(|| extent(&blocks));
+        let placed: BTreeMap<(u32, u32), style::Color> = blocks.into_iter().collect();
+
+        if is_png(output) {
+            save_png(output, &placed, width, height, &options)
+        } else {
+            let options = ansi::AnsiOptions {
+                colors: ansi::AnsiColors::Ansi256,
+                half_blocks: false,
+            };
+            ansi::save_ansi(output, &placed, width, height, &options)
+        }
+    } else {
+        let frames: Vec<BTreeMap<(u32, u32), style::Color>> = document
+            .frames
+            .into_iter()
+            .map(|blocks| blocks.into_iter().collect())
+            .collect();
+
+        /* batch conversions shouldn't touch other files */
+        save_text(output, &frames, document.fps, false)
+    }
+}
+
+/// Convert crossterm::style::Color to image::Rgb<u8>
+pub fn crossterm_to_image_color(color: style::Color) -> Rgb<u8> {
+    match color {
+        style::Color::Black => Rgb([0, 0, 0]),
+        style::Color::DarkGrey => Rgb([64, 64, 64]),
+        style::Color::Grey => Rgb([128, 128, 128]),
+        style::Color::White => Rgb([255, 255, 255]),
+        style::Color::Red => Rgb([255, 0, 0]),
+        style::Color::DarkRed => Rgb([128, 0, 0]),
+        style::Color::Green => Rgb([0, 255, 0]),
+        style::Color::DarkGreen => Rgb([0, 128, 0]),
+        style::Color::Blue => Rgb([0, 0, 255]),
+        style::Color::DarkBlue => Rgb([0, 0, 128]),
+        style::Color::Yellow => Rgb([255, 255, 0]),
+        style::Color::DarkYellow => Rgb([128, 128, 0]),
+        style::Color::Magenta => Rgb([255, 0, 255]),
+        style::Color::DarkMagenta => Rgb([128, 0, 128]),
+        style::Color::Cyan => Rgb([0, 255, 255]),
+        style::Color::DarkCyan => Rgb([0, 128, 128]),
+        style::Color::Rgb { r, g, b } => Rgb([r, g, b]),
+        /* colors of 256-color palette are named or RGB ones */
+        style::Color::AnsiValue(n) => crossterm_to_image_color(palette::color_256(n)),
+        _ => Rgb([0, 0, 0])
+    }
+}
+
+/// Convert image::Rgb<u8> to crossterm::style::Color
+pub fn rgb_to_crossterm_color(rgb: Rgb<u8>) -> style::Color {
+    style::Color::Rgb {
+        r: rgb[0],
+        g: rgb[1],
+        b: rgb[2],
+    }
+}
+
+/// Formats of palette files, chosen by extension
+#[derive(Clone, Copy, PartialEq)]
+pub enum PaletteFormat {
+    /// GIMP palette, `.gpl`
+    Gimp,
+    /// One `rrggbb` per line, `.hex` files from Lospec
+    Hex,
+    /// JASC-PAL, `.pal` files from Paint Shop Pro
+    Jasc,
+}
+
+/// Extensions of palette files, the first one is used when none is typed
+pub const PALETTE_EXTENSIONS: &[&str] = &["gpl", "hex", "pal"];
+
+/// Palette format of a file, files with unknown extension are read as .gpl
+pub fn palette_format(path: &Path) -> PaletteFormat {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "hex" => PaletteFormat::Hex,
+        "pal" => PaletteFormat::Jasc,
+        _ => PaletteFormat::Gimp,
+    }
+}
+
+/// Parse `R G B` numbers at the start of a line, the rest (e.g. color's name) is ignored
+fn parse_rgb_line(line: &str) -> Result<style::Color, String> {
+    let fields: Vec<&str> = line.split_whitespace().take(3).collect();
+    let channels: Vec<u8> = fields
+        .iter()
+        .filter_map(|field| field.parse().ok())
+        .collect();
+
+    match channels[..] {
+        [r, g, b] => Ok(style::Color::Rgb { r, g, b }),
+        _ => Err(format!(
+            "expected 3 numbers 0 - 255 (red, green and blue), found `{}`",
+            line.trim()
+        )),
+    }
+}
+
+/// Parse content of a palette file. Empty palettes are reported as an error
+pub fn parse_palette(
+    content: &str,
+    format: PaletteFormat,
+) -> Result<Vec<style::Color>, Vec<LineError>> {
+    let mut colors: Vec<style::Color> = Vec::new();
+    let mut errors: Vec<LineError> = Vec::new();
+
+    for (i, line) in content.lines().enumerate() {
+        let trimmed = line.trim();
+
+        let result = match format {
+            PaletteFormat::Gimp if i == 0 => match trimmed {
+                "GIMP Palette" => Ok(None),
+                _ => Err("expected `GIMP Palette` header".to_string()),
+            },
+            PaletteFormat::Gimp
+                if trimmed.is_empty()
+                    || trimmed.starts_with('#')
+                    || trimmed.starts_with("Name:")
+                    || trimmed.starts_with("Columns:") =>
+            {
+                Ok(None)
+            }
+            PaletteFormat::Gimp => parse_rgb_line(trimmed).map(Some),
+            PaletteFormat::Hex if trimmed.is_empty() => Ok(None),
+            PaletteFormat::Hex => {
+                let hex = trimmed.trim_start_matches('#');
+                parse_hex(&format!("#{}", hex))
+                    .map(Some)
+                    .ok_or(format!("`{}` is not a color, use rrggbb", trimmed))
+            }
+            PaletteFormat::Jasc if i == 0 => match trimmed {
+                "JASC-PAL" => Ok(None),
+                _ => Err("expected `JASC-PAL` header".to_string()),
+            },
+            /* version and number of colors, number of lines which follow is what counts */
+            PaletteFormat::Jasc if i < 3 || trimmed.is_empty() => Ok(None),
+            PaletteFormat::Jasc => parse_rgb_line(trimmed).map(Some),
+        };
+
+        match result {
+            Ok(Some(color)) => colors.push(color),
+            Ok(None) => {}
+            Err(message) => errors.push(LineError {
+                line: i + 1,
+                message,
+            }),
+        }
+    }
+
+    if colors.is_empty() && errors.is_empty() {
+        errors.push(LineError {
+            line: 1,
+            message: "palette has no colors".to_string(),
+        });
+    }
+
+    if errors.is_empty() {
+        Ok(colors)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Convert colors to palette file of given format
+pub fn palette_to_text(colors: &[style::Color], format: PaletteFormat, name: &str) -> String {
+    let rgb: Vec<Rgb<u8>> = colors
+        .iter()
+        .map(|color| crossterm_to_image_color(*color))
+        .collect();
+
+    let mut string = match format {
+        PaletteFormat::Gimp => format!("GIMP Palette\nName: {}\nColumns: 10\n#\n", name),
+        PaletteFormat::Hex => String::new(),
+        PaletteFormat::Jasc => format!("JASC-PAL\n0100\n{}\n", colors.len()),
+    };
+
+    for c in rgb {
+        string += &match format {
+            PaletteFormat::Gimp => format!(
+                "{:3} {:3} {:3}\t#{:02x}{:02x}{:02x}\n",
+                c[0], c[1], c[2], c[0], c[1], c[2]
+            ),
+            PaletteFormat::Hex => format!("{:02x}{:02x}{:02x}\n", c[0], c[1], c[2]),
+            PaletteFormat::Jasc => format!("{} {} {}\n", c[0], c[1], c[2]),
+        };
+    }
+
+    string
+}
+
+/// Read a palette file, format is chosen by extension
+pub fn read_palette(path: &Path) -> Result<Vec<style::Color>, FileError> {
+    let content = fs::read_to_string(path).map_err(|e| FileError::Read(path.into(), e))?;
+    parse_palette(&content, palette_format(path)).map_err(|e| FileError::Parse(path.into(), e))
+}
+
+/// Save a palette file, format is chosen by extension
+pub fn save_palette(path: &Path, colors: &[style::Color]) -> Result<(), FileError> {
+    let name = path.file_stem().unwrap_or_default().to_string_lossy();
+    let text = palette_to_text(colors, palette_format(path), &name);
+
+    fs::write(path, text).map_err(|e| FileError::Write(path.into(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(r: u8, g: u8, b: u8) -> style::Color {
+        style::Color::Rgb { r, g, b }
+    }
+
+    /// Line numbers of parsing errors
+    fn error_lines(content: &str, size: Option<(u32, u32)>) -> Vec<usize> {
+        match parse_text(content, size) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.iter().map(|e| e.line).collect(),
+        }
+    }
+
+    #[test]
+    fn legacy_file() {
+        let document = parse_text("0 0 10\n\n3 2 19\n", None).ok().unwrap();
+
+        assert_eq!(document.frames.len(), 1);
+        assert_eq!(
+            document.frames[0],
+            vec![
+                ((0, 0), paint::color_from_number(10).unwrap()),
+                ((3, 2), paint::color_from_number(19).unwrap()),
+            ]
+        );
+        assert_eq!(document.fps, DEFAULT_FPS);
+    }
+
+    #[test]
+    fn legacy_errors_have_line_numbers() {
+        let content = "0 0 10\n1 1\nx 0 10\n\n2 2 20\n3 3 11 4\n";
+
+        assert_eq!(error_lines(content, None), vec![2, 3, 5, 6]);
+    }
+
+    #[test]
+    fn blocks_outside_of_canvas() {
+        assert_eq!(
+            error_lines("79 23 10\n80 0 10\n0 24 10\n", Some((80, 24))),
+            vec![2, 3]
+        );
+        assert_eq!(error_lines("65534 0 10\n65535 0 10\n", None), vec![2]);
+    }
+
+    #[test]
+    fn version_3_file() {
+        let content = "GVPAINT 3\npalette 2\nansi 1\n#aa5500\n22 3 1\n23 3 #00ff00\n24 3 0\n";
+        let document = parse_text(content, None).ok().unwrap();
+
+        assert_eq!(
+            document.frames,
+            vec![vec![
+                ((22, 3), rgb(0xaa, 0x55, 0x00)),
+                ((23, 3), rgb(0x00, 0xff, 0x00)),
+                ((24, 3), palette::ansi_color(1)),
+            ]]
+        );
+    }
+
+    #[test]
+    fn version_4_file() {
+        let content = "GVPAINT 4\nfps 12\npalette 1\n#ff8800\n22 3 0\nframe\nframe\n23 3 0\n";
+        let document = parse_text(content, None).ok().unwrap();
+        let orange = rgb(0xff, 0x88, 0x00);
+
+        assert_eq!(document.fps, 12);
+        assert_eq!(
+            document.frames,
+            vec![vec![((22, 3), orange)], vec![], vec![((23, 3), orange)]]
+        );
+    }
+
+    #[test]
+    fn versioned_errors_have_line_numbers() {
+        let content = "GVPAINT 4\nfps 0\npalette 2\nansi 16\n#12345\n0 0 2\n0 0 #zzzzzz\n";
+        assert_eq!(error_lines(content, None), vec![2, 4, 5, 6, 7]);
+
+        assert_eq!(error_lines("GVPAINT 5\n", None), vec![1]);
+        assert_eq!(error_lines("GVPAINT 0\n", None), vec![1]);
+        assert_eq!(error_lines("GVPAINT 2\n", None), vec![1]);
+        assert_eq!(error_lines("GVPAINT\n", None), vec![1]);
+        assert_eq!(error_lines("GVPAINT 3\n0 0 #+12345\n", None), vec![2]);
+        assert_eq!(
+            error_lines("GVPAINT 3\npalette 1\n#+12345\n", None),
+            vec![3]
+        );
+        assert_eq!(
+            error_lines("GVPAINT 3\npalette 2\n#000000\n", None),
+            vec![3]
+        );
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let first: BTreeMap<(u32, u32), style::Color> =
+            [((0, 0), palette::ansi_color(4)), ((5, 1), rgb(1, 2, 3))].into();
+        let second: BTreeMap<(u32, u32), style::Color> = [((2, 2), palette::ansi_color(4))].into();
+
+        for frames in [vec![first.clone()], vec![first, BTreeMap::new(), second]] {
+            let document = parse_text(&painting_to_text(&frames, 5), None)
+                .ok()
+                .unwrap();
+            let parsed: Vec<BTreeMap<(u32, u32), style::Color>> = document
+                .frames
+                .into_iter()
+                .map(|blocks| blocks.into_iter().collect())
+                .collect();
+
+            assert_eq!(parsed, frames);
+        }
+    }
+
+    #[test]
+    fn palette_round_trip() {
+        let colors = vec![rgb(0, 0, 0), rgb(255, 136, 0), rgb(18, 52, 86)];
+
+        for format in [PaletteFormat::Gimp, PaletteFormat::Hex, PaletteFormat::Jasc] {
+            let text = palette_to_text(&colors, format, "test");
+            assert_eq!(parse_palette(&text, format).ok().unwrap(), colors);
+        }
+    }
+
+    #[test]
+    fn palette_files() {
+        let gimp = "GIMP Palette\nName: test\nColumns: 4\n#\n255   0   0\tRed\n  0 255   0\n";
+        let hex = "ff0000\n\n#00ff00\n";
+        let jasc = "JASC-PAL\n0100\n2\n255 0 0\n0 255 0\n";
+        let expected = vec![rgb(255, 0, 0), rgb(0, 255, 0)];
+
+        assert_eq!(
+            parse_palette(gimp, PaletteFormat::Gimp).ok().unwrap(),
+            expected
+        );
+        assert_eq!(
+            parse_palette(hex, PaletteFormat::Hex).ok().unwrap(),
+            expected
+        );
+        assert_eq!(
+            parse_palette(jasc, PaletteFormat::Jasc).ok().unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn palette_errors_have_line_numbers() {
+        let lines = |content: &str, format: PaletteFormat| -> Vec<usize> {
+            match parse_palette(content, format) {
+                Ok(_) => Vec::new(),
+                Err(errors) => errors.iter().map(|e| e.line).collect(),
+            }
+        };
+
+        assert_eq!(lines("GIMP\n1 2 3\n1 2\n", PaletteFormat::Gimp), vec![1, 3]);
+        assert_eq!(lines("ff0000\nred\n", PaletteFormat::Hex), vec![2]);
+        assert_eq!(
+            lines("JASC-PAL\n0100\n1\n256 0 0\n", PaletteFormat::Jasc),
+            vec![4]
+        );
+        assert_eq!(lines("\n", PaletteFormat::Hex), vec![1]);
+    }
+
+    #[test]
+    fn palette_format_by_extension() {
+        assert!(palette_format(Path::new("a.HEX")) == PaletteFormat::Hex);
+        assert!(palette_format(Path::new("a.pal")) == PaletteFormat::Jasc);
+        assert!(palette_format(Path::new("a.gpl")) == PaletteFormat::Gimp);
+        assert!(palette_format(Path::new("a")) == PaletteFormat::Gimp);
+    }
+
+    fn export_options(scale: u32, transparent: bool, crop: bool, aspect: bool) -> ExportOptions {
+        ExportOptions {
+            scale,
+            transparent,
+            crop,
+            aspect,
+            delay: None,
+            loops: 0,
+        }
+    }
+
+    #[test]
+    fn exported_area() {
+        let placed = BTreeMap::from([
+            ((2, 1), rgb(255, 0, 0)),
+            ((4, 2), rgb(0, 0, 255)),
+            ((20, 0), rgb(0, 255, 0)),
+        ]);
+
+        assert_eq!(export_area(&placed, 10, 5, false), ((0, 0), (10, 5)));
+        /* block outside of canvas doesn't count */
+        assert_eq!(export_area(&placed, 10, 5, true), ((2, 1), (3, 2)));
+        assert_eq!(export_area(&BTreeMap::new(), 10, 5, true), ((0, 0), (1, 1)));
+    }
+
+    #[test]
+    fn cropped_and_scaled_export() {
+        let placed = BTreeMap::from([((2, 1), rgb(255, 0, 0)), ((4, 2), rgb(0, 0, 255))]);
+        let img = painting_to_image(&placed, 10, 5, &export_options(2, true, true, true));
+
+        /* 3 x 2 blocks, each 2 pixels wide and 4 tall */
+        assert_eq!(img.dimensions(), (6, 8));
+        assert_eq!(*img.get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+        assert_eq!(*img.get_pixel(1, 3), Rgba([255, 0, 0, 255]));
+        assert_eq!(*img.get_pixel(4, 4), Rgba([0, 0, 255, 255]));
+        assert_eq!(*img.get_pixel(5, 7), Rgba([0, 0, 255, 255]));
+        assert_eq!(img.get_pixel(2, 0)[3], 0);
+        assert_eq!(img.get_pixel(0, 4)[3], 0);
+        assert_eq!(img.get_pixel(3, 7)[3], 0);
+    }
+
+    #[test]
+    fn full_canvas_export() {
+        let placed = BTreeMap::from([((1, 1), rgb(0, 0, 0)), ((3, 0), rgb(1, 2, 3))]);
+        let img = painting_to_image(&placed, 3, 2, &export_options(1, false, false, false));
+
+        /* block right of canvas is skipped, empty cells are white */
+        assert_eq!(img.dimensions(), (3, 2));
+        assert_eq!(*img.get_pixel(1, 1), Rgba([0, 0, 0, 255]));
+        assert_eq!(*img.get_pixel(0, 0), Rgba([255, 255, 255, 255]));
+        assert_eq!(*img.get_pixel(2, 0), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn cropped_frames_stay_lined_up() {
+        let frames = [
+            BTreeMap::from([((1, 1), rgb(255, 0, 0))]),
+            BTreeMap::from([((3, 2), rgb(0, 0, 255))]),
+        ];
+        let images = animation_to_images(&frames, 10, 10, &export_options(1, true, true, false));
+
+        assert_eq!(images.len(), 2);
+        assert!(images.iter().all(|img| img.dimensions() == (3, 2)));
+        assert_eq!(*images[0].get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+        assert_eq!(images[0].get_pixel(2, 1)[3], 0);
+        assert_eq!(*images[1].get_pixel(2, 1), Rgba([0, 0, 255, 255]));
+        assert_eq!(images[1].get_pixel(0, 0)[3], 0);
+    }
+
+    /// Two 2x1 frames, red and blue block on the left
+    fn two_frames() -> Vec<RgbaImage> {
+        let options = export_options(1, true, false, false);
+        let frames = [
+            BTreeMap::from([((0, 0), rgb(255, 0, 0))]),
+            BTreeMap::from([((0, 0), rgb(0, 0, 255))]),
+        ];
+
+        animation_to_images(&frames, 2, 1, &options)
+    }
+
+    #[test]
+    fn gif_frames_delay_and_loops() {
+        use image::{codecs::gif::GifDecoder, AnimationDecoder};
+
+        let mut gif = Vec::new();
+        save_gif(&mut gif, two_frames(), 250, 3).ok().unwrap();
+
+        let frames = GifDecoder::new(io::Cursor::new(&gif))
+            .ok()
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .ok()
+            .unwrap();
+        assert_eq!(frames.len(), 2);
+        assert!(frames
+            .iter()
+            .all(|frame| frame.delay() == Delay::from_numer_denom_ms(250, 1)));
+        assert_eq!(*frames[1].buffer().get_pixel(0, 0), Rgba([0, 0, 255, 255]));
+        assert_eq!(frames[1].buffer().get_pixel(1, 0)[3], 0);
+
+        /* NETSCAPE2.0 block holds the number of repeats after the first play */
+        let netscape = gif.windows(11).position(|w| w == b"NETSCAPE2.0").unwrap();
+        assert_eq!(gif[netscape + 11..netscape + 15], [3, 1, 2, 0]);
+    }
+
+    #[test]
+    fn gif_loops_forever() {
+        let mut gif = Vec::new();
+        save_gif(&mut gif, two_frames(), 100, 0).ok().unwrap();
+
+        let netscape = gif.windows(11).position(|w| w == b"NETSCAPE2.0").unwrap();
+        assert_eq!(gif[netscape + 11..netscape + 15], [3, 1, 0, 0]);
+    }
+
+    #[test]
+    fn apng_frames_delay_and_loops() {
+        let mut apng = Vec::new();
+        save_apng(&mut apng, &two_frames(), 250, 3).ok().unwrap();
+
+        let mut reader = png::Decoder::new(io::Cursor::new(&apng))
+            .read_info()
+            .ok()
+            .unwrap();
+        let control = reader.info().animation_control.unwrap();
+        assert_eq!((control.num_frames, control.num_plays), (2, 3));
+
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let mut frames = 0;
+        while reader.next_frame(&mut buffer).is_ok() {
+            let frame = reader.info().frame_control.unwrap();
+            assert_eq!((frame.delay_num, frame.delay_den), (250, 1000));
+            frames += 1;
+        }
+        assert_eq!(frames, 2);
+        /* last frame read is the blue one */
+        assert_eq!(buffer[..8], [0, 0, 255, 255, 0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,238 @@
+use crossterm::style;
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::layers;
+
+/// Default number of steps kept in undo history
+pub const DEFAULT_DEPTH: usize = 100;
+
+/// Blocks of one frame in every layer, by layer id
+pub type Cels = Vec<(u32, BTreeMap<(u32, u32), style::Color>)>;
+
+/// A single change of the painting
+#[derive(Clone)]
+pub enum Change {
+    /// One cell on the canvas, in layer and frame with given ids.
+    /// `None` means that cell was (or will be) empty
+    Cell {
+        layer: u32,
+        frame: u32,
+        position: (u32, u32),
+        before: Option<style::Color>,
+        after: Option<style::Color>,
+    },
+    /// Layer added to or removed from given place of the stack
+    Layer {
+        index: usize,
+        layer: Box<layers::Layer>,
+        added: bool,
+    },
+    /// Place, visibility, opacity or lock of layer with given id changed
+    Settings {
+        layer: u32,
+        before: layers::Settings,
+        after: layers::Settings,
+    },
+    /// Frame with given id added to or removed from given place of timeline
+    Frame {
+        index: usize,
+        id: u32,
+        cels: Cels,
+        added: bool,
+    },
+}
+
+/// Undo/redo stack, every step is a group of changes
+/// which is undone or redone at once
+pub struct History {
+    pub depth: usize,
+    undo: VecDeque<Vec<Change>>,
+    redo: Vec<Vec<Change>>,
+    group: Option<Vec<Change>>,
+    nesting: usize,
+}
+
+impl History {
+    pub fn new(depth: usize) -> History {
+        History {
+            depth,
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            group: None,
+            nesting: 0,
+        }
+    }
+
+    /// Start collecting changes into one step, e.g. while loading a file.
+    /// Groups can be nested, step is pushed when the outermost one ends
+    pub fn begin_group(&mut self) {
+        self.nesting += 1;
+        if self.group.is_none() {
+            self.group = Some(Vec::new());
+        }
+    }
+
+    /// Finish collecting changes and push them as one step
+    pub fn end_group(&mut self) {
+        self.nesting = self.nesting.saturating_sub(1);
+        if self.nesting == 0 {
+            self.close_groups();
+        }
+    }
+
+    /// Check if changes are being collected into a group
+    pub fn grouping(&self) -> bool {
+        self.group.is_some()
+    }
+
+    /// Finish all open groups at once
+    pub fn close_groups(&mut self) {
+        self.nesting = 0;
+        if let Some(step) = self.group.take() {
+            self.push_step(step);
+        }
+    }
+
+    /// Record changes made at once as one step
+    pub fn record_step(&mut self, changes: Vec<Change>) {
+        self.begin_group();
+        for change in changes {
+            self.record(change);
+        }
+        self.end_group();
+    }
+
+    /// Record a change, either into the open group or as a step of its own
+    pub fn record(&mut self, change: Change) {
+        match self.group.as_mut() {
+            Some(group) => group.push(change),
+            None => self.push_step(vec![change]),
+        }
+    }
+
+    /// Take the last step for undoing, changes should be reverted in reverse order
+    pub fn undo(&mut self) -> Option<Vec<Change>> {
+        let step = self.undo.pop_back()?;
+        self.redo.push(step.clone());
+
+        Some(step)
+    }
+
+    /// Take the last undone step for redoing
+    pub fn redo(&mut self) -> Option<Vec<Change>> {
+        let step = self.redo.pop()?;
+        self.undo.push_back(step.clone());
+
+        Some(step)
+    }
+
+    /// Push a step, undone steps can't be redone after it. With depth 0 nothing is kept
+    fn push_step(&mut self, step: Vec<Change>) {
+        if step.is_empty() {
+            return;
+        }
+
+        self.undo.push_back(step);
+        self.redo.clear();
+
+        while self.undo.len() > self.depth {
+            self.undo.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(x: u32) -> Change {
+        Change::Cell {
+            layer: 1,
+            frame: 1,
+            position: (x, 0),
+            before: None,
+            after: Some(style::Color::Red),
+        }
+    }
+
+    /// X of every cell in a step
+    fn columns(step: Option<Vec<Change>>) -> Vec<u32> {
+        step.unwrap_or_default()
+            .iter()
+            .filter_map(|change| match change {
+                Change::Cell { position, .. } => Some(position.0),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nested_groups_are_one_step() {
+        let mut history = History::new(DEFAULT_DEPTH);
+
+        history.begin_group();
+        history.record(cell(0));
+        history.begin_group();
+        history.record(cell(1));
+        history.end_group();
+        assert!(history.grouping());
+        history.record(cell(2));
+        history.end_group();
+        assert!(!history.grouping());
+
+        assert_eq!(columns(history.undo()), vec![0, 1, 2]);
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn empty_groups_are_not_steps() {
+        let mut history = History::new(DEFAULT_DEPTH);
+        history.record(cell(0));
+
+        history.begin_group();
+        history.end_group();
+
+        assert_eq!(columns(history.undo()), vec![0]);
+    }
+
+    #[test]
+    fn oldest_steps_are_dropped() {
+        let mut history = History::new(2);
+        for x in 0..4 {
+            history.record(cell(x));
+        }
+
+        assert_eq!(columns(history.undo()), vec![3]);
+        assert_eq!(columns(history.undo()), vec![2]);
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn new_step_clears_redo() {
+        let mut history = History::new(DEFAULT_DEPTH);
+        history.record(cell(0));
+        history.record(cell(1));
+
+        assert_eq!(columns(history.undo()), vec![1]);
+        assert_eq!(columns(history.redo()), vec![1]);
+        assert_eq!(columns(history.undo()), vec![1]);
+
+        history.record(cell(2));
+        assert!(history.redo().is_none());
+        assert_eq!(columns(history.undo()), vec![2]);
+        assert_eq!(columns(history.undo()), vec![0]);
+    }
+
+    #[test]
+    fn zero_depth_keeps_nothing() {
+        let mut history = History::new(2);
+        history.record(cell(0));
+        history.undo();
+
+        history.depth = 0;
+        history.record(cell(1));
+
+        assert!(history.undo().is_none());
+        assert!(history.redo().is_none());
+    }
+}
@@ -0,0 +1,486 @@
+use std::path::{Path, PathBuf};
+
+use crossterm::{event::KeyCode, style};
+use image::{imageops, Rgba, RgbaImage};
+
+use crate::file::{self, FileError};
+use crate::layers;
+use crate::paint;
+use crate::render;
+use crate::selection;
+use crate::variables;
+use crate::viewport;
+use crate::window;
+
+/// Width of import window, border included
+const WIDTH: u16 = 49;
+
+/// Height of import window, border included
+const HEIGHT: u16 = 11;
+
+/// Ordered dithering thresholds, 0 - 15
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// How colors which aren't in palette are mixed from the ones which are
+#[derive(Clone, Copy, PartialEq)]
+pub enum Dither {
+    None,
+    FloydSteinberg,
+    Bayer,
+}
+
+impl Dither {
+    fn label(&self) -> &'static str {
+        match self {
+            Dither::None => "none",
+            Dither::FloydSteinberg => "Floyd-Steinberg",
+            Dither::Bayer => "ordered (Bayer 4x4)",
+        }
+    }
+
+    fn next(&self) -> Dither {
+        match self {
+            Dither::None => Dither::FloydSteinberg,
+            Dither::FloydSteinberg => Dither::Bayer,
+            Dither::Bayer => Dither::None,
+        }
+    }
+}
+
+/// Color of imported image which is treated as transparent, besides transparent pixels
+#[derive(Clone, Copy, PartialEq)]
+pub enum KeyColor {
+    None,
+    /// Color of image's top left pixel, usual for sprites
+    TopLeft,
+    /// Color currently used for drawing
+    Current,
+}
+
+impl KeyColor {
+    fn label(&self) -> &'static str {
+        match self {
+            KeyColor::None => "none",
+            KeyColor::TopLeft => "top left pixel",
+            KeyColor::Current => "current color",
+        }
+    }
+
+    fn next(&self) -> KeyColor {
+        match self {
+            KeyColor::None => KeyColor::TopLeft,
+            KeyColor::TopLeft => KeyColor::Current,
+            KeyColor::Current => KeyColor::None,
+        }
+    }
+}
+
+/// Options of .png import, they stay the same until GVPaint is closed
+pub struct ImportOptions {
+    /// Use only colors of loaded palette, nearest one is picked for every pixel
+    pub quantize: bool,
+    pub dither: Dither,
+    /// Scale image down so it fits in canvas, or in the part of it from cursor when
+    /// image floats, otherwise canvas grows
+    pub fit: bool,
+    /// Let image float at cursor until it's stamped, otherwise it's placed at top left corner
+    pub stamp: bool,
+    pub key_color: KeyColor,
+}
+
+/// Scale image down to fit in given size, aspect ratio is kept. Smaller images stay as they are
+fn fit(img: RgbaImage, size: (u32, u32)) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    if width <= size.0 && height <= size.1 {
+        return img;
+    }
+
+    let scale = (size.0 as f64 / width as f64).min(size.1 as f64 / height as f64);
+    let new_width = ((width as f64 * scale) as u32).max(1);
+    let new_height = ((height as f64 * scale) as u32).max(1);
+
+    imageops::resize(&img, new_width, new_height, imageops::FilterType::Triangle)
+}
+
+/// Index of palette color closest to given one
+fn nearest(palette: &[[f32; 3]], rgb: [f32; 3]) -> usize {
+    let distance = |color: &[f32; 3]| -> f32 { (0..3).map(|c| (color[c] - rgb[c]).powi(2)).sum() };
+
+    (0..palette.len())
+        .min_by(|a, b| distance(&palette[*a]).total_cmp(&distance(&palette[*b])))
+        .unwrap_or_default()
+}
+
+/// Replace every pixel with the nearest color of palette, dithering spreads the difference.
+/// Transparent pixels are left out
+pub fn quantize(img: &RgbaImage, colors: &[style::Color], dither: Dither) -> file::Blocks {
+    let palette: Vec<[f32; 3]> = colors
+        .iter()
+        .map(|color| file::crossterm_to_image_color(*color).0.map(|c| c as f32))
+        .collect();
+
+    let (width, height) = img.dimensions();
+    let mut pixels: Vec<[f32; 3]> = img
+        .pixels()
+        .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32])
+        .collect();
+    let mut blocks: file::Blocks = Vec::with_capacity(pixels.len());
+
+    /* fewer colors are further apart, so ordered dithering has to push pixels further */
+    let spread = 255.0 / (colors.len() as f32).cbrt();
+
+    for y in 0..height {
+        for x in 0..width {
+            let mut rgb = pixels[(y * width + x) as usize];
+
+            if dither == Dither::Bayer {
+                let threshold = (BAYER[y as usize % 4][x as usize % 4] as f32 + 0.5) / 16.0 - 0.5;
+                rgb = rgb.map(|c| c + threshold * spread);
+            }
+
+            let index = nearest(&palette, rgb);
+            if img.get_pixel(x, y)[3] >= file::OPAQUE {
+                blocks.push(((x, y), colors[index]));
+            }
+
+            if dither == Dither::FloydSteinberg {
+                let error: Vec<f32> = (0..3).map(|c| rgb[c] - palette[index][c]).collect();
+                let neighbours = [(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)];
+
+                for (dx, dy, weight) in neighbours {
+                    let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                    if nx < 0 || nx >= width as i64 || ny >= height as i64 {
+                        continue;
+                    }
+
+                    let pixel = &mut pixels[(ny * width as i64 + nx) as usize];
+                    for c in 0..3 {
+                        pixel[c] += error[c] * weight / 16.0;
+                    }
+                }
+            }
+        }
+    }
+
+    blocks
+}
+
+/// Make pixels of key color transparent
+fn remove_key_color(img: &mut RgbaImage, key: Rgba<u8>) {
+    for pixel in img.pixels_mut() {
+        if pixel.0[..3] == key.0[..3] {
+            pixel[3] = 0;
+        }
+    }
+}
+
+/// Read .png file with import options, `canvas` is the size image is scaled to fit in,
+/// `colors` the palette it's quantized to and `current` color used for drawing.
+/// Transparent pixels aren't in returned blocks
+pub fn read_png(
+    path: &Path,
+    options: &ImportOptions,
+    canvas: (u32, u32),
+    colors: &[style::Color],
+    current: style::Color,
+) -> Result<file::Blocks, FileError> {
+    let mut img = file::read_rgba(path)?;
+
+    /* before scaling, so edges of transparent areas blend like other pixels */
+    let key = match options.key_color {
+        KeyColor::None => None,
+        KeyColor::TopLeft => img.pixels().next().copied(),
+        KeyColor::Current => {
+            let [r, g, b] = file::crossterm_to_image_color(current).0;
+            Some(Rgba([r, g, b, 255]))
+        }
+    };
+    if let Some(key) = key {
+        remove_key_color(&mut img, key);
+    }
+
+    let img = if options.fit { fit(img, canvas) } else { img };
+
+    if options.quantize && !colors.is_empty() {
+        return Ok(quantize(&img, colors, options.dither));
+    }
+
+    Ok(file::image_to_blocks(&img))
+}
+
+/// Ask how a .png file should be imported before it's opened
+pub fn import_window(
+    state: &mut variables::State,
+    file_menu: &mut variables::FileMenu,
+    path: &Path,
+) {
+    file_menu.import = Some(PathBuf::from(path));
+    state.window_open = true;
+    state.window_open_name = "import".to_string();
+}
+
+/// Position of window's top left corner
+fn origin(viewport: &viewport::Viewport) -> (u16, u16) {
+    (4, viewport.height.saturating_sub(HEIGHT + 2).max(1))
+}
+
+/// Draw import window with current options
+pub fn draw_import_window(
+    renderer: &mut render::Renderer,
+    viewport: &viewport::Viewport,
+    file_menu: &variables::FileMenu,
+) {
+    let Some(path) = file_menu.import.as_ref() else {
+        return;
+    };
+
+    let (x, y) = origin(viewport);
+    let options = &file_menu.import_options;
+    window::draw_border(renderer, x, y, WIDTH, HEIGHT);
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let title: String = format!("Open .png file: {}", name)
+        .chars()
+        .take(WIDTH as usize - 4)
+        .collect();
+    window::print_lines(renderer, x + 2, y + 1, &[&title], style::Color::Red);
+
+    let colors = if options.quantize {
+        "1 - colors: loaded palette"
+    } else {
+        "1 - colors: keep all"
+    };
+    let dither = if options.quantize {
+        format!("2 - dithering: {}", options.dither.label())
+    } else {
+        "2 - dithering: only with palette".to_string()
+    };
+    let fit = if options.fit {
+        "3 - scale down to fit canvas: yes"
+    } else {
+        "3 - scale down to fit canvas: no, canvas grows"
+    };
+    let stamp = if options.stamp {
+        "4 - place: float at cursor, P - stamp"
+    } else {
+        "4 - place: at top left corner"
+    };
+    let key_color = format!("5 - transparent color: {}", options.key_color.label());
+
+    window::print_lines(
+        renderer,
+        x + 2,
+        y + 3,
+        &[colors, &dither, fit, stamp, &key_color],
+        style::Color::DarkYellow,
+    );
+    window::print_lines(
+        renderer,
+        x + 2,
+        y + HEIGHT - 2,
+        &["1 - 5 - change, Enter - open, Esc - cancel"],
+        style::Color::DarkGreen,
+    );
+}
+
+/// Change an option, 0 is the first line of options
+fn toggle_option(options: &mut ImportOptions, line: u16) {
+    match line {
+        0 => options.quantize = !options.quantize,
+        1 => options.dither = options.dither.next(),
+        2 => options.fit = !options.fit,
+        3 => options.stamp = !options.stamp,
+        4 => options.key_color = options.key_color.next(),
+        _ => {}
+    }
+}
+
+/// Open .png file waiting in import window, canvas grows if image doesn't fit in it.
+/// Floating image is placed like pasted blocks, it can be moved before it's stamped
+fn open(
+    canvas: &mut variables::Canvas,
+    runtime: &mut variables::Runtime,
+    state: &mut variables::State,
+    file_menu: &mut variables::FileMenu,
+) {
+    state.window_open = false;
+    let Some(path) = file_menu.import.take() else {
+        return;
+    };
+    if layers::check_locked(runtime, state) {
+        return;
+    }
+
+    /* floating image starts at cursor, so it has to fit in the space right and below it */
+    let cursor = (runtime.cursor_x as u32, runtime.cursor_y as u32);
+    let size = if file_menu.import_options.stamp {
+        (
+            (canvas.width as u32).saturating_sub(cursor.0).max(1),
+            (canvas.height as u32).saturating_sub(cursor.1).max(1),
+        )
+    } else {
+        (canvas.width as u32, canvas.height as u32)
+    };
+    let result = read_png(
+        &path,
+        &file_menu.import_options,
+        size,
+        &runtime.palette.colors,
+        runtime.color,
+    );
+
+    match result {
+        Ok(blocks) if file_menu.import_options.stamp => {
+            let (width, height) = file::extent(&blocks);
+            if !file_menu.import_options.fit {
+                paint::grow_canvas(canvas, (cursor.0 + width, cursor.1 + height));
+            }
+
+            runtime.selection = None;
+            runtime.floating = Some(selection::Floating {
+                blocks,
+                source: None,
+            });
+        }
+        Ok(blocks) => {
+            paint::grow_canvas(canvas, file::extent(&blocks));
+            paint::place_blocks(runtime, blocks);
+        }
+        Err(e) => window::message_box(state, "Error", &e.to_string()),
+    }
+}
+
+/// Handle a key pressed while import window is open
+pub fn import_input(
+    canvas: &mut variables::Canvas,
+    runtime: &mut variables::Runtime,
+    state: &mut variables::State,
+    file_menu: &mut variables::FileMenu,
+    code: KeyCode,
+) {
+    match code {
+        KeyCode::Esc => {
+            file_menu.import = None;
+            state.window_open = false;
+        }
+        KeyCode::Enter => open(canvas, runtime, state, file_menu),
+        KeyCode::Char(c @ '1'..='5') => {
+            toggle_option(&mut file_menu.import_options, c as u16 - '1' as u16)
+        }
+        _ => {}
+    }
+}
+
+/// Handle a mouse click while import window is open, clicked option is changed
+pub fn import_click(
+    viewport: &viewport::Viewport,
+    file_menu: &mut variables::FileMenu,
+    column: u16,
+    row: u16,
+) {
+    let (x, y) = origin(viewport);
+    if column <= x || column >= x + WIDTH - 1 || row < y + 3 {
+        return;
+    }
+
+    toggle_option(&mut file_menu.import_options, row - y - 3);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: style::Color = style::Color::Rgb { r: 0, g: 0, b: 0 };
+    const WHITE: style::Color = style::Color::Rgb {
+        r: 255,
+        g: 255,
+        b: 255,
+    };
+
+    /// Opaque grey image, every pixel `value`
+    fn grey(width: u32, height: u32, value: u8) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba([value, value, value, 255]))
+    }
+
+    fn whites(blocks: &file::Blocks) -> usize {
+        blocks.iter().filter(|(_, color)| *color == WHITE).count()
+    }
+
+    #[test]
+    fn nearest_palette_color() {
+        let palette = [[0.0, 0.0, 0.0], [255.0, 255.0, 255.0], [255.0, 0.0, 0.0]];
+
+        assert_eq!(nearest(&palette, [10.0, 20.0, 5.0]), 0);
+        assert_eq!(nearest(&palette, [200.0, 190.0, 210.0]), 1);
+        assert_eq!(nearest(&palette, [200.0, 40.0, 30.0]), 2);
+        assert_eq!(nearest(&[], [1.0, 2.0, 3.0]), 0);
+    }
+
+    #[test]
+    fn quantize_without_dithering() {
+        let mut img = RgbaImage::from_fn(4, 1, |x, _| {
+            let value = (x * 85) as u8;
+            Rgba([value, value, value, 255])
+        });
+        img.put_pixel(3, 0, Rgba([255, 255, 255, 0]));
+
+        let blocks = quantize(&img, &[BLACK, WHITE], Dither::None);
+
+        assert_eq!(
+            blocks,
+            vec![((0, 0), BLACK), ((1, 0), BLACK), ((2, 0), WHITE)]
+        );
+    }
+
+    #[test]
+    fn floyd_steinberg_mixes_grey() {
+        let blocks = quantize(&grey(8, 8, 128), &[BLACK, WHITE], Dither::FloydSteinberg);
+
+        assert_eq!(blocks.len(), 64);
+        assert!((28..=36).contains(&whites(&blocks)));
+        /* neighbours differ, a flat area would be a single color without dithering */
+        assert!(blocks.windows(2).any(|pair| pair[0].1 != pair[1].1));
+    }
+
+    #[test]
+    fn bayer_follows_brightness() {
+        let dark = quantize(&grey(4, 4, 64), &[BLACK, WHITE], Dither::Bayer);
+        let middle = quantize(&grey(4, 4, 128), &[BLACK, WHITE], Dither::Bayer);
+        let light = quantize(&grey(4, 4, 192), &[BLACK, WHITE], Dither::Bayer);
+
+        /* half of thresholds are above middle grey */
+        assert_eq!(whites(&middle), 8);
+        assert!(0 < whites(&dark) && whites(&dark) < 8);
+        assert!(8 < whites(&light) && whites(&light) < 16);
+        assert_eq!(
+            whites(&quantize(&grey(4, 4, 0), &[BLACK, WHITE], Dither::Bayer)),
+            0
+        );
+        assert_eq!(
+            whites(&quantize(&grey(4, 4, 255), &[BLACK, WHITE], Dither::Bayer)),
+            16
+        );
+    }
+
+    #[test]
+    fn fit_keeps_aspect_ratio() {
+        assert_eq!(fit(grey(40, 20, 0), (10, 10)).dimensions(), (10, 5));
+        assert_eq!(fit(grey(20, 40, 0), (10, 30)).dimensions(), (10, 20));
+        assert_eq!(fit(grey(100, 1, 0), (10, 10)).dimensions(), (10, 1));
+        assert_eq!(fit(grey(8, 6, 0), (10, 10)).dimensions(), (8, 6));
+    }
+
+    #[test]
+    fn key_color_becomes_transparent() {
+        let mut img = grey(2, 2, 0);
+        img.put_pixel(1, 0, Rgba([255, 0, 0, 255]));
+        img.put_pixel(0, 1, Rgba([255, 0, 0, 128]));
+
+        remove_key_color(&mut img, Rgba([255, 0, 0, 255]));
+
+        assert_eq!(img.get_pixel(0, 0)[3], 255);
+        assert_eq!(img.get_pixel(1, 0)[3], 0);
+        assert_eq!(img.get_pixel(0, 1)[3], 0);
+        assert_eq!(file::image_to_blocks(&img).len(), 2);
+    }
+}
@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, MouseButton, MouseEventKind};
+
+use crate::paint::KEY;
+
+/// What mouse did on the canvas
+pub enum MouseAction {
+    /// Left button was pressed or dragged
+    Place,
+    /// Right button was pressed or dragged
+    Erase,
+    /// Any button was released
+    Release,
+}
+
+/// Input event which paint loop reacts to
+pub enum Input {
+    Key(KeyCode),
+    Mouse {
+        action: MouseAction,
+        column: u16,
+        row: u16,
+    },
+    /// Terminal was resized to given number of columns and rows
+    Resize(u16, u16),
+    /// Nothing happened before timeout, next frame of animation is due
+    Tick,
+    None,
+}
+
+/// Read a next event from terminal, raw mode and mouse capture
+/// are enabled for whole session in `main`
+pub fn read_input(timeout: Option<Duration>) -> Input {
+    if let Some(timeout) = timeout {
+        if !event::poll(timeout).expect("Failed to poll event") {
+            return Input::Tick;
+        }
+    }
+
+    match event::read().expect("Failed to read event") {
+        Event::Key(key_event) if key_event.kind != KeyEventKind::Release => {
+            Input::Key(key_event.code)
+        }
+        Event::Mouse(mouse_event) => {
+            let action = match mouse_event.kind {
+                MouseEventKind::Down(MouseButton::Left)
+                | MouseEventKind::Drag(MouseButton::Left) => MouseAction::Place,
+                MouseEventKind::Down(MouseButton::Right)
+                | MouseEventKind::Drag(MouseButton::Right) => MouseAction::Erase,
+                MouseEventKind::Up(_) => MouseAction::Release,
+                _ => return Input::None,
+            };
+
+            Input::Mouse {
+                action,
+                column: mouse_event.column,
+                row: mouse_event.row,
+            }
+        }
+        Event::Resize(columns, rows) => Input::Resize(columns, rows),
+        _ => Input::None,
+    }
+}
+
+/// Return an enum value of a pressed key
+pub fn key_from_code(code: KeyCode) -> KEY {
+    let KeyCode::Char(ch) = code else {
+        return KEY::NONE;
+    };
+
+    let ch_u32: u32 = ch as u32;
+    match ch_u32 {
+        119 | 87 => KEY::W,
+        115 | 83 => KEY::S,
+        97 | 65 => KEY::A,
+        100 | 68 => KEY::D,
+        102 | 70 => KEY::FILE,
+        104 | 72 => KEY::HELP,
+        112 | 80 => KEY::PLACE,
+        101 | 69 => KEY::ERASE,
+        113 | 81 => KEY::QUIT,
+        117 | 85 => KEY::UNDO,
+        114 | 82 => KEY::REDO,
+        98 | 66 => KEY::FILL,
+        110 | 78 => KEY::NEIGHBOURS,
+        108 | 76 => KEY::LINE,
+        111 | 79 => KEY::RECTANGLE,
+        105 | 73 => KEY::ELLIPSE,
+        109 | 77 => KEY::MARK,
+        99 | 67 => KEY::COPY,
+        120 | 88 => KEY::CUT,
+        118 | 86 => KEY::PASTE,
+        103 | 71 => KEY::MOVE,
+        106 | 74 => KEY::JUMP,
+        43 | 61 => KEY::ZOOMIN,
+        45 | 95 => KEY::ZOOMOUT,
+        107 | 75 => KEY::PALETTE,
+        91 | 123 => KEY::PREVPAGE,
+        93 | 125 => KEY::NEXTPAGE,
+        121 | 89 => KEY::LAYERS,
+        116 | 84 => KEY::TIMELINE,
+        44 | 60 => KEY::PREVFRAME,
+        46 | 62 => KEY::NEXTFRAME,
+        49 => KEY::C1,
+        50 => KEY::C2,
+        51 => KEY::C3,
+        52 => KEY::C4,
+        53 => KEY::C5,
+        54 => KEY::C6,
+        55 => KEY::C7,
+        56 => KEY::C8,
+        57 => KEY::C9,
+        48 => KEY::C0,
+        _ => KEY::NONE,
+    }
+}
@@ -0,0 +1,847 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Range,
+};
+
+use crossterm::{event::KeyCode, style};
+
+use crate::file;
+use crate::history;
+use crate::render;
+use crate::variables;
+use crate::viewport;
+use crate::window;
+
+/// Width of layers window, border included
+const WIDTH: u16 = 49;
+
+/// Height of layers window, border included
+const HEIGHT: u16 = 16;
+
+/// Number of layers visible in layers window at once
+const VISIBLE_LAYERS: usize = 8;
+
+/// Longest layer name
+const NAME_LENGTH: usize = 16;
+
+/// Opacity of previous frame shown under the current one
+const ONION_OPACITY: u8 = 30;
+
+/// One layer of painting, blocks of upper layers cover the ones below.
+/// Every frame has its own blocks (`cels`), they're found by frame's id.
+/// `id` stays the same when layers are reordered, undo history uses it
+#[derive(Clone)]
+pub struct Layer {
+    pub id: u32,
+    pub name: String,
+    pub cels: BTreeMap<u32, BTreeMap<(u32, u32), style::Color>>,
+    pub visible: bool,
+    /// 0 - 100 %
+    pub opacity: u8,
+    pub locked: bool,
+}
+
+/// Place of a layer in the stack and how it's shown, undo history keeps them
+#[derive(Clone, Copy, PartialEq)]
+pub struct Settings {
+    pub index: usize,
+    pub visible: bool,
+    pub opacity: u8,
+    pub locked: bool,
+}
+
+impl Layer {
+    /// Blocks of this layer in frame with given id
+    pub fn cel_mut(&mut self, frame: u32) -> &mut BTreeMap<(u32, u32), style::Color> {
+        self.cels.entry(frame).or_default()
+    }
+}
+
+/// Stack of layers, the first one is at the bottom, and timeline of frames they share.
+/// Blocks are placed into `active` layer in `frame`, `frames` hold ids of frames in order.
+/// `rename` holds name being typed in layers window
+pub struct Layers {
+    pub stack: Vec<Layer>,
+    pub active: usize,
+    pub next_id: u32,
+    pub rename: Option<String>,
+    pub frames: Vec<u32>,
+    pub frame: usize,
+    pub next_frame_id: u32,
+    /// Previous frame is shown dimmed under the current one
+    pub onion: bool,
+    /// Frames per second of playback
+    pub fps: u32,
+    pub playing: bool,
+}
+
+impl Layers {
+    /// Single layer holding given frames, at least one frame is made
+    pub fn from_frames(frames: Vec<BTreeMap<(u32, u32), style::Color>>, fps: u32) -> Layers {
+        let count = frames.len().max(1) as u32;
+        let mut layers = Layers {
+            stack: Vec::new(),
+            active: 0,
+            next_id: 1,
+            rename: None,
+            frames: (1..=count).collect(),
+            frame: 0,
+            next_frame_id: count + 1,
+            onion: false,
+            fps,
+            playing: false,
+        };
+
+        layers.add(BTreeMap::new());
+        for (id, blocks) in (1..).zip(frames) {
+            layers.stack[0].cels.insert(id, blocks);
+        }
+
+        layers
+    }
+
+    /// Layer blocks are placed into
+    pub fn active(&self) -> &Layer {
+        &self.stack[self.active]
+    }
+
+    pub fn active_mut(&mut self) -> &mut Layer {
+        &mut self.stack[self.active]
+    }
+
+    /// Id of current frame
+    pub fn frame_id(&self) -> u32 {
+        self.frames[self.frame]
+    }
+
+    /// Blocks of active layer in current frame
+    pub fn blocks(&self) -> &BTreeMap<(u32, u32), style::Color> {
+        static EMPTY: BTreeMap<(u32, u32), style::Color> = BTreeMap::new();
+        self.active().cels.get(&self.frame_id()).unwrap_or(&EMPTY)
+    }
+
+    /// Layer with given id, it's `None` once the layer is deleted
+    pub fn by_id_mut(&mut self, id: u32) -> Option<&mut Layer> {
+        self.stack.iter_mut().find(|layer| layer.id == id)
+    }
+
+    /// Add a layer above the active one and make it active, given blocks are in current frame
+    pub fn add(&mut self, blocks: BTreeMap<(u32, u32), style::Color>) {
+        let mut layer = Layer {
+            id: self.next_id,
+            name: format!("Layer {}", self.next_id),
+            cels: BTreeMap::new(),
+            visible: true,
+            opacity: 100,
+            locked: false,
+        };
+        layer.cels.insert(self.frame_id(), blocks);
+        self.next_id += 1;
+
+        let index = (self.active + 1).min(self.stack.len());
+        self.stack.insert(index, layer);
+        self.active = index;
+    }
+
+    /// Delete active layer, the last one can't be deleted. Returned change brings it back
+    pub fn delete(&mut self) -> Option<history::Change> {
+        let index = self.active;
+        let layer = self.remove_layer(self.active().id)?;
+
+        Some(history::Change::Layer {
+            index,
+            layer: Box::new(layer),
+            added: false,
+        })
+    }
+
+    /// Put a layer into given place of the stack and make it active, used by undo
+    pub fn insert_layer(&mut self, index: usize, layer: Layer) {
+        let index = index.min(self.stack.len());
+        self.stack.insert(index, layer);
+        self.active = index;
+    }
+
+    /// Take layer with given id out of the stack, the one below it becomes active.
+    /// The last layer can't be taken
+    pub fn remove_layer(&mut self, id: u32) -> Option<Layer> {
+        let index = self.stack.iter().position(|layer| layer.id == id)?;
+        if self.stack.len() < 2 {
+            return None;
+        }
+
+        let layer = self.stack.remove(index);
+        self.active = index.saturating_sub(1);
+
+        Some(layer)
+    }
+
+    /// Move active layer one step up or down the stack
+    pub fn move_active(&mut self, up: bool) {
+        let target = if up {
+            self.active + 1
+        } else {
+            self.active.wrapping_sub(1)
+        };
+
+        if target < self.stack.len() {
+            self.stack.swap(self.active, target);
+            self.active = target;
+        }
+    }
+
+    /// Settings of active layer
+    pub fn settings(&self) -> Settings {
+        let layer = self.active();
+
+        Settings {
+            index: self.active,
+            visible: layer.visible,
+            opacity: layer.opacity,
+            locked: layer.locked,
+        }
+    }
+
+    /// Move layer with given id to its place and give it the rest of settings, it becomes active.
+    /// Used by undo
+    pub fn apply_settings(&mut self, id: u32, settings: Settings) {
+        let Some(index) = self.stack.iter().position(|layer| layer.id == id) else {
+            return;
+        };
+
+        let mut layer = self.stack.remove(index);
+        layer.visible = settings.visible;
+        layer.opacity = settings.opacity;
+        layer.locked = settings.locked;
+
+        let index = settings.index.min(self.stack.len());
+        self.stack.insert(index, layer);
+        self.active = index;
+    }
+
+    /// Merge active layer into the one below it in every frame. Blocks over blocks of the lower
+    /// layer are blended as they look on screen, the others are moved as they are, so empty
+    /// cells stay empty. Hidden layer isn't merged, neither is one above a locked layer.
+    /// Returned changes undo it, there are none when nothing was merged
+    pub fn merge_down(&mut self) -> Vec<history::Change> {
+        if self.active == 0 || !self.active().visible || self.stack[self.active - 1].locked {
+            return Vec::new();
+        }
+
+        let index = self.active;
+        let upper = self.stack.remove(index);
+        self.active -= 1;
+
+        let lower = &mut self.stack[self.active];
+        let id = lower.id;
+        let mut changes = Vec::new();
+        for (frame, blocks) in &upper.cels {
+            let cel = lower.cel_mut(*frame);
+            for (position, color) in blocks {
+                let before = cel.get(position).copied();
+                let after = match before {
+                    Some(_) => blend(before, *color, upper.opacity),
+                    None => *color,
+                };
+                cel.insert(*position, after);
+
+                changes.push(history::Change::Cell {
+                    layer: id,
+                    frame: *frame,
+                    position: *position,
+                    before,
+                    after: Some(after),
+                });
+            }
+        }
+
+        /* upper layer comes back after cells of the lower one are undone */
+        changes.insert(
+            0,
+            history::Change::Layer {
+                index,
+                layer: Box::new(upper),
+                added: false,
+            },
+        );
+
+        changes
+    }
+
+    /// Add a frame after the current one and go to it. Duplicated frame
+    /// starts with blocks of the current one, otherwise it's empty.
+    /// Returned change removes it again
+    pub fn add_frame(&mut self, duplicate: bool) -> history::Change {
+        let current = self.frame_id();
+        let id = self.next_frame_id;
+        self.next_frame_id += 1;
+
+        let cels: history::Cels = self
+            .stack
+            .iter()
+            .map(|layer| match duplicate {
+                true => (layer.id, layer.cels.get(&current).cloned().unwrap_or_default()),
+                false => (layer.id, BTreeMap::new()),
+            })
+            .collect();
+        self.insert_frame(self.frame + 1, id, &cels);
+
+        history::Change::Frame {
+            index: self.frame,
+            id,
+            cels,
+            added: true,
+        }
+    }
+
+    /// Delete current frame, the last one can't be deleted. Returned change brings it back
+    pub fn delete_frame(&mut self) -> Option<history::Change> {
+        let index = self.frame;
+        let id = self.frame_id();
+        let cels = self.remove_frame(id)?;
+
+        Some(history::Change::Frame {
+            index,
+            id,
+            cels,
+            added: false,
+        })
+    }
+
+    /// Put a frame with given blocks into given place of timeline and go to it, used by undo
+    pub fn insert_frame(&mut self, index: usize, id: u32, cels: &history::Cels) {
+        for (layer, blocks) in cels {
+            if let Some(layer) = self.by_id_mut(*layer) {
+                layer.cels.insert(id, blocks.clone());
+            }
+        }
+
+        let index = index.min(self.frames.len());
+        self.frames.insert(index, id);
+        self.frame = index;
+    }
+
+    /// Take frame with given id out of timeline together with its blocks in every layer.
+    /// The last frame can't be taken
+    pub fn remove_frame(&mut self, id: u32) -> Option<history::Cels> {
+        let index = self.frames.iter().position(|frame| *frame == id)?;
+        if self.frames.len() < 2 {
+            return None;
+        }
+
+        self.frames.remove(index);
+        let cels = self
+            .stack
+            .iter_mut()
+            .filter_map(|layer| layer.cels.remove(&id).map(|blocks| (layer.id, blocks)))
+            .collect();
+        self.frame = index.min(self.frames.len() - 1);
+
+        Some(cels)
+    }
+
+    /// Go to next or previous frame, timeline wraps around
+    pub fn turn_frame(&mut self, next: bool) {
+        let count = self.frames.len();
+        self.frame = match next {
+            true => (self.frame + 1) % count,
+            false => (self.frame + count - 1) % count,
+        };
+    }
+
+    /// Blocks of visible layers in given frame and columns merged together, as they look
+    /// on screen. `hidden` cells of active layer are left out, e.g. selection which is being moved
+    pub fn composite(
+        &self,
+        frame: usize,
+        columns: Range<u32>,
+        hidden: &[(u32, u32)],
+    ) -> BTreeMap<(u32, u32), style::Color> {
+        let mut result: BTreeMap<(u32, u32), style::Color> = BTreeMap::new();
+        let hidden: BTreeSet<&(u32, u32)> = hidden.iter().collect();
+        let id = self.frames[frame];
+
+        for (i, layer) in self.stack.iter().enumerate() {
+            if !layer.visible || layer.opacity == 0 {
+                continue;
+            }
+            let Some(blocks) = layer.cels.get(&id) else {
+                continue;
+            };
+
+            for (position, color) in blocks.range((columns.start, 0)..(columns.end, 0)) {
+                if i == self.active && hidden.contains(position) {
+                    continue;
+                }
+
+                let below = result.get(position).copied();
+                result.insert(*position, blend(below, *color, layer.opacity));
+            }
+        }
+
+        result
+    }
+
+    /// Previous frame in given columns, dimmed so it can be told apart from current one
+    pub fn onion_skin(&self, columns: Range<u32>) -> BTreeMap<(u32, u32), style::Color> {
+        if !self.onion || self.frame == 0 {
+            return BTreeMap::new();
+        }
+
+        let mut blocks = self.composite(self.frame - 1, columns, &[]);
+        for color in blocks.values_mut() {
+            *color = blend(None, *color, ONION_OPACITY);
+        }
+
+        blocks
+    }
+
+    /// All visible layers of given frame merged into one, used when painting is saved
+    pub fn flatten(&self, frame: usize) -> BTreeMap<(u32, u32), style::Color> {
+        self.composite(frame, 0..u32::MAX, &[])
+    }
+
+    /// Every frame with its visible layers merged into one
+    pub fn flatten_frames(&self) -> Vec<BTreeMap<(u32, u32), style::Color>> {
+        (0..self.frames.len())
+            .map(|frame| self.flatten(frame))
+            .collect()
+    }
+}
+
+/// Color of a block with given opacity over another one, empty cells are white.
+/// Fully opaque colors are kept as they are
+fn blend(below: Option<style::Color>, color: style::Color, opacity: u8) -> style::Color {
+    if opacity >= 100 {
+        return color;
+    }
+
+    let below = file::crossterm_to_image_color(below.unwrap_or(style::Color::White));
+    let above = file::crossterm_to_image_color(color);
+    let alpha = opacity as u32;
+    let channel =
+        |c: usize| ((above[c] as u32 * alpha + below[c] as u32 * (100 - alpha)) / 100) as u8;
+
+    style::Color::Rgb {
+        r: channel(0),
+        g: channel(1),
+        b: channel(2),
+    }
+}
+
+/// Open or close layers window
+pub fn layers_window(runtime: &mut variables::Runtime, state: &mut variables::State) {
+    runtime.layers.rename = None;
+    window::toggle_window(state, "layers");
+}
+
+/// Position of window's top left corner
+fn origin(viewport: &viewport::Viewport) -> (u16, u16) {
+    (4, viewport.height.saturating_sub(HEIGHT + 2).max(1))
+}
+
+/// Index of the topmost layer shown in window, active one is always visible
+fn scroll(layers: &Layers) -> usize {
+    /* window lists layers from the top of the stack */
+    let row = layers.stack.len() - 1 - layers.active;
+    (row + 1).saturating_sub(VISIBLE_LAYERS)
+}
+
+/// Draw layers window, the topmost layer is listed first
+pub fn draw_layers_window(
+    renderer: &mut render::Renderer,
+    viewport: &viewport::Viewport,
+    runtime: &variables::Runtime,
+) {
+    let (x, y) = origin(viewport);
+    let layers = &runtime.layers;
+    window::draw_border(renderer, x, y, WIDTH, HEIGHT);
+
+    window::print_lines(
+        renderer,
+        x + 2,
+        y + 1,
+        &["Layers - click or W / S - select, Esc - close"],
+        style::Color::Red,
+    );
+
+    let scroll = scroll(layers);
+    for (row, (i, layer)) in layers
+        .stack
+        .iter()
+        .enumerate()
+        .rev()
+        .skip(scroll)
+        .take(VISIBLE_LAYERS)
+        .enumerate()
+    {
+        let name: String = layer.name.chars().take(NAME_LENGTH).collect();
+        let line = format!(
+            "{} {:<width$} {:<6} {:>3}% {}",
+            if i == layers.active { ">" } else { " " },
+            name,
+            if layer.visible { "shown" } else { "hidden" },
+            layer.opacity,
+            if layer.locked { "locked" } else { "" },
+            width = NAME_LENGTH
+        );
+        let color = if i == layers.active {
+            style::Color::DarkYellow
+        } else {
+            style::Color::Red
+        };
+
+        window::print_lines(renderer, x + 2, y + 2 + row as u16, &[&line], color);
+    }
+
+    if let Some(name) = &layers.rename {
+        window::print_lines(
+            renderer,
+            x + 2,
+            y + 11,
+            &[&format!("Name: {}_", name), "Enter - rename, Esc - cancel"],
+            style::Color::DarkYellow,
+        );
+    } else {
+        window::print_lines(
+            renderer,
+            x + 2,
+            y + 11,
+            &[
+                "N - new, X - delete, M - merge down",
+                "U / J - move up / down, R - rename",
+                "V - show / hide, L - lock, + / - opacity",
+            ],
+            style::Color::DarkGreen,
+        );
+    }
+}
+
+/// Handle a key pressed while layers window is open
+pub fn layers_input(runtime: &mut variables::Runtime, state: &mut variables::State, code: KeyCode) {
+    let layers = &mut runtime.layers;
+
+    if let Some(name) = layers.rename.as_mut() {
+        match code {
+            KeyCode::Esc => layers.rename = None,
+            KeyCode::Enter => {
+                let name = name.trim().to_string();
+                if !name.is_empty() {
+                    layers.active_mut().name = name;
+                }
+                layers.rename = None;
+            }
+            KeyCode::Backspace => {
+                name.pop();
+            }
+            KeyCode::Char(c) if name.chars().count() < NAME_LENGTH => name.push(c),
+            _ => {}
+        }
+
+        return;
+    }
+
+    let KeyCode::Char(c) = code else {
+        if let KeyCode::Esc = code {
+            state.window_open = false;
+        }
+        if let KeyCode::Up = code {
+            layers.active = (layers.active + 1).min(layers.stack.len() - 1);
+        }
+        if let KeyCode::Down = code {
+            layers.active = layers.active.saturating_sub(1);
+        }
+        return;
+    };
+
+    let id = layers.active().id;
+    let before = layers.settings();
+
+    match c.to_ascii_lowercase() {
+        'w' => layers.active = (layers.active + 1).min(layers.stack.len() - 1),
+        's' => layers.active = layers.active.saturating_sub(1),
+        'n' => {
+            layers.add(BTreeMap::new());
+            runtime.history.record(history::Change::Layer {
+                index: layers.active,
+                layer: Box::new(layers.active().clone()),
+                added: true,
+            });
+        }
+        'x' => runtime
+            .history
+            .record_step(layers.delete().into_iter().collect()),
+        'm' => runtime.history.record_step(layers.merge_down()),
+        'r' => layers.rename = Some(layers.active().name.clone()),
+        'u' => layers.move_active(true),
+        'j' => layers.move_active(false),
+        'v' => layers.active_mut().visible = !layers.active().visible,
+        'l' => layers.active_mut().locked = !layers.active().locked,
+        '+' | '=' => layers.active_mut().opacity = (layers.active().opacity + 10).min(100),
+        '-' | '_' => layers.active_mut().opacity = layers.active().opacity.saturating_sub(10),
+        'q' | 'y' => state.window_open = false,
+        _ => {}
+    }
+
+    /* reordering, hiding, locking and opacity are undone like changes of blocks */
+    if "ujvl+=-_".contains(c.to_ascii_lowercase()) && layers.settings() != before {
+        runtime.history.record(history::Change::Settings {
+            layer: id,
+            before,
+            after: layers.settings(),
+        });
+    }
+}
+
+/// Handle a mouse click while layers window is open, clicked layer becomes active
+pub fn layers_click(runtime: &mut variables::Runtime, column: u16, row: u16) {
+    let (x, y) = origin(&runtime.viewport);
+    if column <= x || column >= x + WIDTH - 1 || row < y + 2 {
+        return;
+    }
+
+    let layers = &mut runtime.layers;
+    let row = (row - y - 2) as usize;
+    let listed = scroll(layers) + row;
+
+    if row < VISIBLE_LAYERS && listed < layers.stack.len() {
+        layers.active = layers.stack.len() - 1 - listed;
+    }
+}
+
+/// Check if active layer is locked before blocks are placed into it, user is told in a message box
+pub fn check_locked(runtime: &variables::Runtime, state: &mut variables::State) -> bool {
+    let locked = runtime.layers.active().locked;
+    if locked {
+        window::message_box(state, "Error", "Active layer is locked");
+    }
+
+    locked
+}
+
+/// Text shown on the first row: current frame, active layer and whether it's locked
+pub fn status(runtime: &variables::Runtime) -> String {
+    let layers = &runtime.layers;
+    let layer = layers.active();
+
+    format!(
+        "Frame {}/{}{} | Layer {}/{}: {}{}",
+        layers.frame + 1,
+        layers.frames.len(),
+        if layers.playing { " (playing)" } else { "" },
+        layers.active + 1,
+        layers.stack.len(),
+        layer.name,
+        if layer.locked { " (locked)" } else { "" }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: style::Color = style::Color::Rgb { r: 255, g: 0, b: 0 };
+    const BLUE: style::Color = style::Color::Rgb { r: 0, g: 0, b: 255 };
+
+    /// Two layers in one frame, red block at (0, 0) in the lower one and
+    /// blue blocks at (0, 0) and (1, 0) in the upper one, which is active
+    fn two_layers() -> Layers {
+        let mut layers = Layers::from_frames(vec![BTreeMap::from([((0, 0), RED)])], 12);
+        layers.add(BTreeMap::from([((0, 0), BLUE), ((1, 0), BLUE)]));
+
+        layers
+    }
+
+    #[test]
+    fn blend_colors() {
+        assert!(blend(Some(RED), BLUE, 100) == BLUE);
+        assert!(blend(Some(RED), BLUE, 0) == RED);
+        assert!(
+            blend(Some(RED), BLUE, 50)
+                == style::Color::Rgb {
+                    r: 127,
+                    g: 0,
+                    b: 127
+                }
+        );
+        assert!(
+            blend(None, RED, 50)
+                == style::Color::Rgb {
+                    r: 255,
+                    g: 127,
+                    b: 127
+                }
+        );
+    }
+
+    #[test]
+    fn composite_of_visible_layers() {
+        let mut layers = two_layers();
+        assert!(layers.flatten(0) == BTreeMap::from([((0, 0), BLUE), ((1, 0), BLUE)]));
+
+        layers.stack[1].opacity = 50;
+        let purple = style::Color::Rgb {
+            r: 127,
+            g: 0,
+            b: 127,
+        };
+        let light_blue = style::Color::Rgb {
+            r: 127,
+            g: 127,
+            b: 255,
+        };
+        assert!(layers.flatten(0) == BTreeMap::from([((0, 0), purple), ((1, 0), light_blue)]));
+
+        /* cells being moved are left out of active layer, columns limit the area */
+        assert!(layers.composite(0, 0..1, &[(0, 0)]) == BTreeMap::from([((0, 0), RED)]));
+
+        layers.stack[1].visible = false;
+        assert!(layers.flatten(0) == BTreeMap::from([((0, 0), RED)]));
+    }
+
+    #[test]
+    fn onion_skin_shows_previous_frame() {
+        let mut layers =
+            Layers::from_frames(vec![BTreeMap::from([((2, 0), BLUE)]), BTreeMap::new()], 12);
+        assert!(layers.onion_skin(0..10).is_empty());
+
+        layers.onion = true;
+        assert!(layers.onion_skin(0..10).is_empty());
+
+        layers.frame = 1;
+        let dimmed = style::Color::Rgb {
+            r: 178,
+            g: 178,
+            b: 255,
+        };
+        assert!(layers.onion_skin(0..10) == BTreeMap::from([((2, 0), dimmed)]));
+        assert!(layers.onion_skin(0..2).is_empty());
+    }
+
+    #[test]
+    fn merge_down_blends_only_over_blocks() {
+        let mut layers = two_layers();
+        layers.stack[1].opacity = 50;
+
+        let changes = layers.merge_down();
+
+        assert_eq!(layers.stack.len(), 1);
+        assert_eq!(layers.active, 0);
+        /* blue over red is blended, block over empty cell is moved as it is */
+        let purple = style::Color::Rgb {
+            r: 127,
+            g: 0,
+            b: 127,
+        };
+        assert!(*layers.blocks() == BTreeMap::from([((0, 0), purple), ((1, 0), BLUE)]));
+
+        assert_eq!(changes.len(), 3);
+        assert!(matches!(
+            changes[0],
+            history::Change::Layer {
+                index: 1,
+                added: false,
+                ..
+            }
+        ));
+        assert!(matches!(
+            changes[2],
+            history::Change::Cell {
+                position: (1, 0),
+                before: None,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn hidden_or_locked_layers_are_not_merged() {
+        let mut layers = two_layers();
+        layers.active_mut().visible = false;
+        assert!(layers.merge_down().is_empty());
+        assert_eq!(layers.stack.len(), 2);
+
+        layers.active_mut().visible = true;
+        layers.stack[0].locked = true;
+        assert!(layers.merge_down().is_empty());
+        assert_eq!(layers.stack.len(), 2);
+
+        layers.active = 0;
+        layers.stack[0].locked = false;
+        assert!(layers.merge_down().is_empty());
+    }
+
+    #[test]
+    fn settings_are_restored() {
+        let mut layers = two_layers();
+        let id = layers.active().id;
+        let before = layers.settings();
+
+        layers.move_active(false);
+        layers.active_mut().visible = false;
+        layers.active_mut().opacity = 40;
+        layers.active = 1;
+
+        layers.apply_settings(id, before);
+
+        assert_eq!(layers.active, 1);
+        assert_eq!(layers.active().id, id);
+        assert!(layers.settings() == before);
+    }
+
+    #[test]
+    fn undone_frame_deletion_restores_cels() {
+        let mut layers = Layers::from_frames(
+            vec![
+                BTreeMap::from([((0, 0), RED)]),
+                BTreeMap::from([((1, 0), RED)]),
+                BTreeMap::from([((2, 0), RED)]),
+            ],
+            12,
+        );
+        layers.frame = 1;
+        layers.add(BTreeMap::from([((1, 1), BLUE)]));
+        let before: Vec<_> = layers
+            .stack
+            .iter()
+            .map(|layer| layer.cels.clone())
+            .collect();
+
+        let Some(history::Change::Frame {
+            index,
+            id,
+            cels,
+            added: false,
+        }) = layers.delete_frame()
+        else {
+            panic!("frame wasn't deleted");
+        };
+        assert_eq!(layers.frames, vec![1, 3]);
+        assert!(layers
+            .stack
+            .iter()
+            .all(|layer| !layer.cels.contains_key(&id)));
+
+        /* undo puts it back in its place, redo takes it out again */
+        layers.insert_frame(index, id, &cels);
+        assert_eq!(layers.frames, vec![1, 2, 3]);
+        assert_eq!(layers.frame, 1);
+        assert!(layers
+            .stack
+            .iter()
+            .map(|layer| &layer.cels)
+            .eq(before.iter()));
+
+        assert!(layers.remove_frame(id).is_some());
+        assert_eq!(layers.frames, vec![1, 3]);
+    }
+
+    #[test]
+    fn last_frame_is_kept() {
+        let mut layers = two_layers();
+
+        assert!(layers.delete_frame().is_none());
+        assert_eq!(layers.frames, vec![1]);
+        assert_eq!(layers.blocks().len(), 2);
+    }
+}
@@ -5,64 +5,228 @@ use std::{
     io::{stdout, Write}, process::exit
 };
 
-use crossterm::{style, terminal, QueueableCommand};
+use crossterm::{cursor, event, style, terminal, ExecutableCommand, QueueableCommand};
 
+mod ansi;
+mod cli;
+mod dialog;
+mod export;
+mod file;
+mod history;
+mod import;
+mod input;
+mod layers;
 mod paint;
+mod palette;
+mod render;
+mod selection;
+mod timeline;
+mod tools;
 mod variables;
+mod vga;
+mod viewport;
 mod window;
 
 /// Entry point for program
 fn main() {
-    clearscreen::clear().expect("Failed to clean screen!");
-    let mut stdout = stdout();
+    let arguments = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(arguments) => arguments,
+        Err(e) => {
+            eprintln!("gvpaint: {}\n\n{}", e, cli::USAGE);
+            exit(1);
+        }
+    };
 
-    let size = terminal::size().unwrap();
-    let x = size.0;
-    let y = size.1;
+    let file = match arguments.command {
+        cli::Command::Help => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        cli::Command::Convert { input, output } => {
+            /* headless, terminal isn't touched at all */
+            if let Err(e) = file::convert(&input, &output, arguments.size) {
+                eprintln!("gvpaint: {}", e);
+                exit(1);
+            }
 
-    let mut i = 0;
-    let mut j = 0;
+            return;
+        }
+        cli::Command::Check { input } => {
+            /* without --size, coordinates are checked against the largest canvas */
+            let size = arguments.size.map(|(w, h)| (w as u32, h as u32));
+            match file::read_painting(&input, size) {
+                Ok(document) => println!(
+                    "{}: {} blocks in {} frames, no problems found",
+                    input.display(),
+                    document.frames.iter().map(Vec::len).sum::<usize>(),
+                    document.frames.len()
+                ),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit(1);
+                }
+            }
 
-    stdout.queue(style::SetBackgroundColor(style::Color::White));
+            return;
+        }
+        cli::Command::Paint { file } => file,
+    };
+
+    let terminal_size = terminal::size().unwrap();
 
-    while i < y {
-        while j < x {
-            println!(" ");
-            i += 1;
-            j += 1;
+    let mut document = file::Document {
+        frames: vec![Vec::new()],
+        fps: file::DEFAULT_FPS,
+    };
+    if let Some(path) = &file {
+        let size = arguments.size.map(|(w, h)| (w as u32, h as u32));
+        match file::read_painting(path, size) {
+            Ok(opened) => document = opened,
+            Err(e) => {
+                eprintln!("gvpaint: {}", e);
+                exit(1);
+            }
         }
     }
 
+    /* canvas is as large as --size, or large enough for the opened file and screen */
     let mut canvas = variables::Canvas {
-        width: x,
-        height: y,
+        width: terminal_size.0.max(1),
+        height: terminal_size.1.saturating_sub(2).max(1),
     };
+    match arguments.size {
+        Some((width, height)) => {
+            canvas.width = width;
+            canvas.height = height;
+        }
+        None => paint::grow_canvas(&mut canvas, document.extent()),
+    }
+
+    clearscreen::clear().expect("Failed to clean screen!");
+    let mut stdout = stdout();
+
+    let x = canvas.width;
+    let y = canvas.height;
+
+    let x_2 = (x as f64) / 2.2;
+    let y_2 = (y as f64) / 2.2;
 
-    let x_2 = (x as f64) / (2.2 as f64);
-    let y_2 = (y as f64) / (2.2 as f64);
+    /* depth of undo history can be changed with --history or GVPAINT_HISTORY_DEPTH */
+    let history_depth: usize = arguments.history_depth.unwrap_or(
+        std::env::var("GVPAINT_HISTORY_DEPTH")
+            .ok()
+            .and_then(|depth| depth.parse().ok())
+            .unwrap_or(history::DEFAULT_DEPTH),
+    );
 
-    let mut placed: BTreeMap<(u32, u32), style::Color> = BTreeMap::new();
     let mut runtime = variables::Runtime {
         cursor_x: x_2,
         cursor_y: y_2,
         cursor_color: style::Color::Black,
         color: style::Color::Green,
         last_pressed_key: paint::KEY::NONE,
-        placed: placed,
+        layers: layers::Layers::from_frames(
+            document
+                .frames
+                .into_iter()
+                .map(|blocks| blocks.into_iter().collect())
+                .collect(),
+            document.fps,
+        ),
+        history: history::History::new(history_depth),
+        fill_diagonal: false,
+        shape: None,
+        selection: None,
+        clipboard: Vec::new(),
+        floating: None,
+        viewport: viewport::Viewport {
+            x: 0,
+            y: 0,
+            width: terminal_size.0,
+            height: terminal_size.1,
+            zoom: viewport::Zoom::Normal,
+        },
+        palette: palette::Palette {
+            input: String::new(),
+            error: None,
+            recent: Vec::new(),
+            colors: palette::default_colors(),
+            page: 0,
+        },
     };
 
+    /* large canvas is scrolled so cursor is in the middle of screen */
+    let cursor = (runtime.cursor_x as u32, runtime.cursor_y as u32);
+    runtime.viewport.center(&canvas, cursor);
+
     let mut state = variables::State {
         window_open: false,
         window_open_name: "none".to_string(),
+        message: None,
+        jump: String::new(),
     };
 
     let mut file_menu = variables::FileMenu {
-        file_content: " ".to_string(),
+        dialog: None,
+        last_path: file,
+        import: None,
+        import_options: import::ImportOptions {
+            quantize: false,
+            dither: import::Dither::None,
+            fit: false,
+            stamp: true,
+            key_color: import::KeyColor::None,
+        },
+        export: None,
+        export_frames: false,
+        export_options: export::ExportOptions {
+            scale: 1,
+            transparent: false,
+            crop: false,
+            aspect: false,
+            delay: None,
+            loops: 0,
+        },
+        ansi: None,
+        ansi_open: false,
+        ansi_options: ansi::AnsiOptions {
+            colors: ansi::AnsiColors::Ansi256,
+            half_blocks: false,
+        },
+        vga: None,
+        vga_options: vga::VgaOptions {
+            size: (80, 25),
+            half_blocks: false,
+            bright_background: false,
+        },
     };
 
+    /* panic shouldn't leave terminal in raw mode and painted in canvas colors */
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        default_hook(info);
+    }));
+
+    terminal::enable_raw_mode().expect("Failed to enable raw mode");
+    stdout.execute(event::EnableMouseCapture);
+    /* renderer draws its own cursor as a block */
+    stdout.execute(cursor::Hide);
+
     paint::paint(&mut canvas, &mut runtime, &mut state, &mut file_menu);
 
+    restore_terminal();
+}
+
+/// Give terminal back in the state it was before GVPaint started
+fn restore_terminal() {
+    let mut stdout = stdout();
+
+    stdout.execute(event::DisableMouseCapture);
+    stdout.execute(cursor::Show);
+    terminal::disable_raw_mode();
+
     stdout.queue(style::SetBackgroundColor(style::Color::Reset));
     stdout.queue(style::SetForegroundColor(style::Color::Reset));
-    clearscreen::clear().expect("Failed to clean screen!");
+    clearscreen::clear();
 }
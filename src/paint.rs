@@ -3,16 +3,26 @@ use std::{
     io::{self, prelude::*, stdout, Stdout},
 };
 
-use crossterm::{
-    cursor,
-    event::{self, Event, KeyCode},
-    style::{self, Color, SetForegroundColor, SetStyle},
-    terminal::{self, disable_raw_mode, enable_raw_mode}, QueueableCommand,
-};
-
+use crossterm::style::{self, Color};
+
+use crate::ansi;
+use crate::dialog;
+use crate::export;
+use crate::history;
+use crate::import;
+use crate::input::{self, Input, MouseAction};
+use crate::layers;
+use crate::palette;
+use crate::render;
+use crate::selection;
+use crate::timeline;
+use crate::tools;
 use crate::variables;
+use crate::vga;
 use crate::window;
 
+/* key names are written like in PekOS-GV */
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Clone)]
 pub enum KEY {
     NONE,
@@ -35,115 +45,134 @@ pub enum KEY {
     C8,
     C9,
     C0,
+    UNDO,
+    REDO,
+    FILL,
+    NEIGHBOURS,
+    LINE,
+    RECTANGLE,
+    ELLIPSE,
+    MARK,
+    COPY,
+    CUT,
+    PASTE,
+    MOVE,
+    JUMP,
+    ZOOMIN,
+    ZOOMOUT,
+    PALETTE,
+    PREVPAGE,
+    NEXTPAGE,
+    LAYERS,
+    TIMELINE,
+    PREVFRAME,
+    NEXTFRAME,
 }
 
 /// BIOS color attribute equivalent of crossterm colors used below.
 /// L - light; D - dark
-enum COLOR {
-    BLACK,
-    DBLUE,
-    LGREEN,
-    LCYAN,
-    LRED,
-    LMAGENTA,
-    BROWN,
-    LGREY,
-    YELLOW,
-    WHITE,
-}
-
-/// Read a character from user input
-fn read_user_input_character() -> Option<char> {
-    enable_raw_mode().expect("Failed to enable raw mode");
-
-    let result = if let Event::Key(key_event) = event::read().expect("Failed to read event") {
-        match key_event.code {
-            KeyCode::Char(c) => Some(c),
-            _ => None,
-        }
-    } else {
-        None
-    };
-
-    disable_raw_mode().expect("Failed to disable raw mode");
-
-    result
-}
-
-/// Return an enum value of a pressed key
-fn cursor_input() -> KEY {
-    if let Some(ch) = read_user_input_character()
-    {
-        let ch_u32: u32 = ch as u32;
-        match ch_u32 {
-            119 | 87 => return KEY::W,
-            115 | 83 => return KEY::S,
-            97 | 65 => return KEY::A,
-            100 | 68 => return KEY::D,
-            102 | 70 => return KEY::FILE,
-            104 | 72 => return KEY::HELP,
-            112 | 80 => return KEY::PLACE,
-            101 | 69 => return KEY::ERASE,
-            113 | 81 => return KEY::QUIT,
-            49 => return KEY::C1,
-            50 => return KEY::C2,
-            51 => return KEY::C3,
-            52 => return KEY::C4,
-            53 => return KEY::C5,
-            54 => return KEY::C6,
-            55 => return KEY::C7,
-            56 => return KEY::C8,
-            57 => return KEY::C9,
-            48 => return KEY::C0,
-            _ => return KEY::NONE,
-        }
-    }
-
-    return KEY::NONE;
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy)]
+pub enum COLOR {
+    BLACK = 0x0,
+    DBLUE = 0x1,
+    LGREEN = 0xA,
+    LCYAN = 0xB,
+    LRED = 0xC,
+    LMAGENTA = 0xD,
+    BROWN = 0x6,
+    LGREY = 0x7,
+    YELLOW = 0xE,
+    WHITE = 0xF,
 }
 
 /// Convert KEY to crossterm::style::Color
 pub fn return_color(key: KEY) -> Color {
     match key {
-        KEY::C1 => return style::Color::Black,
-        KEY::C2 => return style::Color::Blue,
-        KEY::C3 => return style::Color::Green,
-        KEY::C4 => return style::Color::Cyan,
-        KEY::C5 => return style::Color::Red,
-        KEY::C6 => return style::Color::Magenta,
-        KEY::C7 => return style::Color::Rgb { // brown
+        KEY::C1 => style::Color::Black,
+        KEY::C2 => style::Color::Blue,
+        KEY::C3 => style::Color::Green,
+        KEY::C4 => style::Color::Cyan,
+        KEY::C5 => style::Color::Red,
+        KEY::C6 => style::Color::Magenta,
+        KEY::C7 => style::Color::Rgb { // brown
             r: 170, 
             g: 85, 
             b: 0 
         },
-        KEY::C8 => return style::Color::Grey,
-        KEY::C9 => return style::Color::Yellow,
-        KEY::C0 => return style::Color::White,
-        _ => return style::Color::Black,
+        KEY::C8 => style::Color::Grey,
+        KEY::C9 => style::Color::Yellow,
+        KEY::C0 => style::Color::White,
+        _ => style::Color::Black,
     }
 }
 
+/// Convert color number used in text files (10 - 19) to crossterm::style::Color
+pub fn color_from_number(number: u32) -> Option<Color> {
+    let key = match number {
+        10 => KEY::C1,
+        11 => KEY::C2,
+        12 => KEY::C3,
+        13 => KEY::C4,
+        14 => KEY::C5,
+        15 => KEY::C6,
+        16 => KEY::C7,
+        17 => KEY::C8,
+        18 => KEY::C9,
+        19 => KEY::C0,
+        _ => return None,
+    };
+
+    Some(return_color(key))
+}
+
 /// Convert crossterm::style::Color to KEY
 pub fn return_color_int(color: style::Color) -> KEY {
     match color {
-        style::Color::Black => return KEY::C1,
-        style::Color::Blue => return KEY::C2,
-        style::Color::Green => return KEY::C3,
-        style::Color::Cyan => return KEY::C4,
-        style::Color::Red => return KEY::C5,
-        style::Color::Magenta => return KEY::C6,
+        style::Color::Black => KEY::C1,
+        style::Color::Blue => KEY::C2,
+        style::Color::Green => KEY::C3,
+        style::Color::Cyan => KEY::C4,
+        style::Color::Red => KEY::C5,
+        style::Color::Magenta => KEY::C6,
         style::Color::Rgb { // brown
             r: 170,
             g: 85,
             b: 0,
-        } => return KEY::C7,
-        style::Color::Grey => return KEY::C8,
-        style::Color::Yellow => return KEY::C9,
-        style::Color::White => return KEY::C0,
-        _ => return KEY::C1,
+        } => KEY::C7,
+        style::Color::Grey => KEY::C8,
+        style::Color::Yellow => KEY::C9,
+        style::Color::White => KEY::C0,
+        _ => KEY::C1,
     }
 }
 
+/// Convert crossterm::style::Color to BIOS color, only colors of keys 1 - 0 have one
+pub fn return_bios_color(color: style::Color) -> Option<COLOR> {
+    let bios = match return_color_int(color) {
+        KEY::C1 => COLOR::BLACK,
+        KEY::C2 => COLOR::DBLUE,
+        KEY::C3 => COLOR::LGREEN,
+        KEY::C4 => COLOR::LCYAN,
+        KEY::C5 => COLOR::LRED,
+        KEY::C6 => COLOR::LMAGENTA,
+        KEY::C7 => COLOR::BROWN,
+        KEY::C8 => COLOR::LGREY,
+        KEY::C9 => COLOR::YELLOW,
+        KEY::C0 => COLOR::WHITE,
+        _ => return None,
+    };
+
+    /* every other color is returned as key 1 */
+    if let COLOR::BLACK = bios {
+        if color != style::Color::Black {
+            return None;
+        }
+    }
+
+    Some(bios)
+}
+
 /// Update cursor position, but DON'T draw it on the new position. 
 /// Based on last pressed key (W, S, A, D)
 fn move_cursor_blkey(runtime: &mut variables::Runtime) {
@@ -156,105 +185,360 @@ fn move_cursor_blkey(runtime: &mut variables::Runtime) {
     }
 }
 
-/// Remove an old cursor. If painting is below, redraw it
-fn remove_old_cursor(stdout: &mut Stdout, runtime: &mut variables::Runtime) {
-    stdout.queue(cursor::MoveTo(
-        runtime.cursor_x as u16,
-        runtime.cursor_y as u16,
-    ));
-    stdout.queue(style::SetForegroundColor(style::Color::White));
-    println!("\u{2588}");
-
-    let mut placed = runtime.placed.clone();
-
-    for (k, v) in placed {
-        if (runtime.cursor_x as u32) == k.0 {
-            /* we can just check any axis, x or y */
-            stdout.queue(cursor::MoveTo(k.0 as u16, k.1 as u16));
-            stdout.queue(style::SetForegroundColor(v));
-            println!("\u{2588}");
-        }
+/// Resize renderer and viewport to new terminal size
+fn resize(
+    canvas: &variables::Canvas,
+    runtime: &mut variables::Runtime,
+    renderer: &mut render::Renderer,
+    size: (u16, u16),
+) {
+    renderer.resize(size.0, size.1);
+    runtime.viewport.width = size.0;
+    runtime.viewport.height = size.1;
+
+    place_new_cursor(canvas, runtime);
+}
+
+/// Keep cursor inside canvas, screen scrolls when it reaches an edge
+pub fn place_new_cursor(canvas: &variables::Canvas, runtime: &mut variables::Runtime) {
+    runtime.cursor_x = runtime.cursor_x.clamp(0.0, canvas.width as f64 - 1.0);
+    runtime.cursor_y = runtime.cursor_y.clamp(0.0, canvas.height as f64 - 1.0);
+
+    let cursor = (runtime.cursor_x as u32, runtime.cursor_y as u32);
+    runtime.viewport.follow(canvas, cursor);
+}
+
+/// Zoom in or out, screen is scrolled so cursor is in its middle
+fn zoom(canvas: &variables::Canvas, runtime: &mut variables::Runtime, closer: bool) {
+    let viewport = &mut runtime.viewport;
+    viewport.zoom = if closer {
+        viewport.zoom.closer()
+    } else {
+        viewport.zoom.farther()
+    };
+
+    let cursor = (runtime.cursor_x as u32, runtime.cursor_y as u32);
+    runtime.viewport.center(canvas, cursor);
+}
+
+/// Grow canvas so it's at least of given size, e.g. when opened file doesn't fit in it
+pub fn grow_canvas(canvas: &mut variables::Canvas, size: (u32, u32)) {
+    canvas.width = canvas.width.max(size.0.min(u16::MAX as u32) as u16);
+    canvas.height = canvas.height.max(size.1.min(u16::MAX as u32) as u16);
+}
+
+/// Place a blok into active layer, locked layer stays as it is.
+/// Nothing is recorded in history when the cell already has that color
+pub fn place_blok(runtime: &mut variables::Runtime) {
+    let frame = runtime.layers.frame_id();
+    let layer = runtime.layers.active_mut();
+    if layer.locked {
+        return;
+    }
+
+    let position = (runtime.cursor_x as u32, runtime.cursor_y as u32);
+    let before = layer.cel_mut(frame).insert(position, runtime.color);
+    if before == Some(runtime.color) {
+        return;
     }
+
+    runtime.history.record(history::Change::Cell {
+        layer: layer.id,
+        frame,
+        position,
+        before,
+        after: Some(runtime.color),
+    });
 }
 
-/// Place a new cursor
-fn place_new_cursor(
-    stdout: &mut Stdout,
-    canvas: &mut variables::Canvas,
-    runtime: &mut variables::Runtime,
-) {
-    if (runtime.cursor_y as u16) != (canvas.height - 2) {
-        stdout.queue(cursor::MoveTo(
-            runtime.cursor_x as u16,
-            runtime.cursor_y as u16,
-        ));
-        stdout.queue(style::SetForegroundColor(runtime.cursor_color));
-        println!("\u{2588}");
+/// Place many blocks at once, cursor and color stay as they were.
+/// All of them are one step in undo history
+pub fn place_blocks(runtime: &mut variables::Runtime, blocks: Vec<((u32, u32), style::Color)>) {
+    let current_x = runtime.cursor_x;
+    let current_y = runtime.cursor_y;
+    let current_color = runtime.color;
+
+    runtime.history.begin_group();
+    for ((x, y), color) in blocks {
+        runtime.cursor_x = x as f64;
+        runtime.cursor_y = y as f64;
+        runtime.color = color;
+
+        place_blok(runtime);
+    }
+    runtime.history.end_group();
+
+    runtime.cursor_x = current_x;
+    runtime.cursor_y = current_y;
+    runtime.color = current_color;
+}
+
+/// Erase a blok from active layer, the cell becomes empty again.
+/// Nothing is recorded in history when it was empty already
+pub fn erase_blok(runtime: &mut variables::Runtime) {
+    let frame = runtime.layers.frame_id();
+    let layer = runtime.layers.active_mut();
+    if layer.locked {
+        return;
+    }
+
+    let position = (runtime.cursor_x as u32, runtime.cursor_y as u32);
+    let before = layer.cel_mut(frame).remove(&position);
+    if before.is_none() {
+        return;
+    }
+
+    runtime.history.record(history::Change::Cell {
+        layer: layer.id,
+        frame,
+        position,
+        before,
+        after: None,
+    });
+}
+
+/// Undo or redo a step from history
+fn apply_history(runtime: &mut variables::Runtime, undo: bool) {
+    let step = if undo {
+        runtime.history.undo()
     } else {
-        remove_old_cursor(stdout, runtime);
+        runtime.history.redo()
+    };
+
+    let Some(mut changes) = step else {
+        return;
+    };
+
+    if undo {
+        changes.reverse();
+    }
+
+    for change in changes {
+        match change {
+            history::Change::Cell {
+                layer,
+                frame,
+                position,
+                before,
+                after,
+            } => {
+                let color = if undo { before } else { after };
+
+                /* changes of deleted layers and frames are gone with them */
+                let Some(blocks) = runtime
+                    .layers
+                    .by_id_mut(layer)
+                    .and_then(|layer| layer.cels.get_mut(&frame))
+                else {
+                    continue;
+                };
+
+                match color {
+                    Some(c) => blocks.insert(position, c),
+                    None => blocks.remove(&position),
+                };
+            }
+            /* added layer or frame is removed by undo, removed one comes back */
+            history::Change::Layer {
+                index,
+                layer,
+                added,
+            } => {
+                if added == undo {
+                    runtime.layers.remove_layer(layer.id);
+                } else {
+                    runtime.layers.insert_layer(index, *layer);
+                }
+            }
+            history::Change::Settings {
+                layer,
+                before,
+                after,
+            } => {
+                let settings = if undo { before } else { after };
+                runtime.layers.apply_settings(layer, settings);
+            }
+            history::Change::Frame {
+                index,
+                id,
+                cels,
+                added,
+            } => {
+                if added == undo {
+                    runtime.layers.remove_frame(id);
+                } else {
+                    runtime.layers.insert_frame(index, id, &cels);
+                }
+            }
+        }
+    }
+}
+
+/// Start a shape anchored at cursor, or change the one being drawn.
+/// Same kind again toggles between outline and filled
+fn start_shape(runtime: &mut variables::Runtime, kind: tools::ShapeKind) {
+    match runtime.shape.as_mut() {
+        Some(shape) if shape.kind == kind => shape.filled = !shape.filled,
+        Some(shape) => shape.kind = kind,
+        None => {
+            runtime.shape = Some(tools::Shape {
+                kind,
+                anchor: (runtime.cursor_x as u32, runtime.cursor_y as u32),
+                filled: false,
+            })
+        }
+    }
+}
 
-        runtime.cursor_y -= 2.0;
-        place_new_cursor(stdout, canvas, runtime);
+/// Place a shape being drawn from its anchor to cursor as one undo step, or throw it away
+fn finish_shape(canvas: &variables::Canvas, runtime: &mut variables::Runtime, confirm: bool) {
+    let Some(shape) = runtime.shape.take() else {
+        return;
+    };
+
+    if confirm {
+        let end = (runtime.cursor_x as u32, runtime.cursor_y as u32);
+        let blocks = tools::rasterize(canvas, &shape, end)
+            .into_iter()
+            .map(|position| (position, runtime.color))
+            .collect();
+
+        place_blocks(runtime, blocks);
     }
 }
 
-/// Place a blok
-pub fn place_blok(stdout: &mut Stdout, runtime: &mut variables::Runtime) {
-    runtime.placed.insert(
-        (runtime.cursor_x as u32, runtime.cursor_y as u32),
-        runtime.color,
-    );
-    stdout.queue(cursor::MoveTo(
-        runtime.cursor_x as u16,
-        runtime.cursor_y as u16,
-    ));
-    stdout.queue(style::SetForegroundColor(runtime.color));
-    println!("\u{2588}");
+/// Close a window, returns true if there was no window and program should exit
+fn close(state: &mut variables::State) -> bool {
+    if state.window_open && state.window_open_name == "help" {
+        window::help_window(state);
+    } else if state.window_open && state.window_open_name == "file" {
+        window::file_window(state);
+    } else if state.window_open && state.window_open_name == "message" {
+        state.window_open = false;
+        state.message = None;
+    } else {
+        return true;
+    }
+
+    false
 }
 
-/// Close a window or exit the program
-fn close(
-    stdout: &mut Stdout,
+/// Place or erase a block where mouse was clicked or dragged.
+/// Clicks inside file window pick its entries instead
+fn mouse_input(
     canvas: &mut variables::Canvas,
     runtime: &mut variables::Runtime,
     state: &mut variables::State,
+    file_menu: &mut variables::FileMenu,
+    action: MouseAction,
+    position: (u16, u16),
 ) {
-    if state.window_open == true && state.window_open_name == "help" {
-        window::help_window(stdout, canvas, runtime, state);
-    } else if state.window_open == true && state.window_open_name == "file" {
-        window::file_window(stdout, canvas, runtime, state);
-    } else {
-        std::process::exit(0);
+    let (column, row) = position;
+
+    if let MouseAction::Release = action {
+        /* whole stroke is undone at once */
+        runtime.history.close_groups();
+        return;
     }
-}
 
-/// Redraw a canvas, keep a painting intact
-pub fn redraw_canvas(stdout: &mut Stdout, runtime: &mut variables::Runtime) {
-    clearscreen::clear().expect("Failed to clear screen!");
-
-    stdout.queue(style::SetForegroundColor(style::Color::Red));
-    stdout.queue(cursor::MoveTo(0, 0));
-    println!("Press 'H' or 'h' for help!");
-
-    let mut placed = runtime.placed.clone();
-    for (k, v) in placed {
-        if k.0 != 0 {
-            /* we can just check any axis, x or y */
-            stdout.queue(cursor::MoveTo(k.0 as u16, k.1 as u16));
-            stdout.queue(style::SetForegroundColor(v));
-            println!("\u{2588}");
+    if state.window_open && state.window_open_name == "file" {
+        if let (MouseAction::Place, Some(entry)) =
+            (&action, window::file_window_entry(&runtime.viewport, column, row))
+        {
+            dialog::open_dialog(state, file_menu, entry);
         }
+
+        return;
     }
 
-    stdout.queue(cursor::EnableBlinking);
-    stdout.queue(cursor::MoveTo(
-        runtime.cursor_x as u16,
-        runtime.cursor_y as u16,
-    ));
-    stdout.queue(style::SetForegroundColor(runtime.cursor_color));
-    println!("\u{2588}");
+    if state.window_open && state.window_open_name == "dialog" {
+        if let MouseAction::Place = action {
+            dialog::dialog_click(&runtime.viewport, file_menu, column, row);
+        }
+
+        return;
+    }
+
+    if state.window_open && state.window_open_name == "palette" {
+        if let MouseAction::Place = action {
+            palette::palette_click(runtime, state, column, row);
+        }
 
-    return;
+        return;
+    }
+
+    if state.window_open && state.window_open_name == "layers" {
+        if let MouseAction::Place = action {
+            layers::layers_click(runtime, column, row);
+        }
+
+        return;
+    }
+
+    if state.window_open && state.window_open_name == "timeline" {
+        if let MouseAction::Place = action {
+            timeline::timeline_click(runtime, column, row);
+        }
+
+        return;
+    }
+
+    if state.window_open && state.window_open_name == "import" {
+        if let MouseAction::Place = action {
+            import::import_click(&runtime.viewport, file_menu, column, row);
+        }
+
+        return;
+    }
+
+    if state.window_open && state.window_open_name == "export" {
+        if let MouseAction::Place = action {
+            export::export_click(&runtime.viewport, file_menu, column, row);
+        }
+
+        return;
+    }
+
+    if state.window_open && state.window_open_name == "ansi" {
+        if let MouseAction::Place = action {
+            ansi::ansi_click(&runtime.viewport, file_menu, column, row);
+        }
+
+        return;
+    }
+
+    if state.window_open && state.window_open_name == "vga" {
+        if let MouseAction::Place = action {
+            vga::vga_click(&runtime.viewport, file_menu, column, row);
+        }
+
+        return;
+    }
+
+    /* help, jump and message windows don't take clicks, canvas under them stays as it is */
+    if state.window_open {
+        return;
+    }
+
+    /* first row holds help text and the last one position indicator */
+    let Some(position) = runtime.viewport.to_canvas(column, row) else {
+        return;
+    };
+    if !tools::in_canvas(canvas, (position.0 as i64, position.1 as i64)) {
+        return;
+    }
+
+    if !runtime.history.grouping() {
+        runtime.history.begin_group();
+    }
+
+    runtime.cursor_x = position.0 as f64;
+    runtime.cursor_y = position.1 as f64;
+
+    match action {
+        MouseAction::Place => place_blok(runtime),
+        _ => erase_blok(runtime),
+    }
 }
 
 /// Entry function for drawing
@@ -265,79 +549,216 @@ pub fn paint(
     file_menu: &mut variables::FileMenu,
 ) {
     let mut stdout: Stdout = stdout();
+    let mut renderer =
+        render::Renderer::new(runtime.viewport.width, runtime.viewport.height);
+    place_new_cursor(canvas, runtime);
 
-    stdout.queue(style::SetForegroundColor(style::Color::Red));
-    stdout.queue(cursor::MoveTo(0, 0));
-    println!("Press 'H' or 'h' for help!");
+    loop {
+        /* every frame is drawn whole, only changed cells reach the terminal */
+        render::draw_frame(&mut renderer, canvas, runtime, state, file_menu);
+        renderer.flush(&mut stdout).expect("Failed to draw on screen!");
 
-    stdout.queue(cursor::EnableBlinking);
-    stdout.queue(cursor::MoveTo(
-        runtime.cursor_x as u16,
-        runtime.cursor_y as u16,
-    ));
-    stdout.queue(style::SetForegroundColor(runtime.cursor_color));
-    println!("\u{2588}");
+        let input = input::read_input(timeline::frame_duration(runtime));
 
-    loop {
-        let key: KEY = cursor_input();
+        if let Input::Resize(columns, rows) = input {
+            resize(canvas, runtime, &mut renderer, (columns, rows));
+            continue;
+        }
+
+        if runtime.layers.playing {
+            /* frames change on their own, any key or click stops playback */
+            match input {
+                Input::Tick => runtime.layers.turn_frame(true),
+                Input::Key(_) | Input::Mouse { .. } => runtime.layers.playing = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        if window::too_small(&runtime.viewport) {
+            /* nothing fits on screen, only exit works */
+            if let Input::Key(code) = input {
+                if let KEY::QUIT = input::key_from_code(code) {
+                    return;
+                }
+            }
+            continue;
+        }
+
+        if state.window_open && state.window_open_name == "message" {
+            /* message box is closed by any key or click */
+            if let Input::Key(_) | Input::Mouse { action: MouseAction::Place, .. } = input {
+                close(state);
+            }
+            continue;
+        }
+
+        let key: KEY = match input {
+            Input::Key(code) => {
+                if state.window_open && state.window_open_name == "dialog" {
+                    /* dialog takes all keys, user types a file name there */
+                    dialog::dialog_input(canvas, runtime, state, file_menu, code);
+                    continue;
+                }
+                if state.window_open && state.window_open_name == "jump" {
+                    window::jump_input(canvas, runtime, state, code);
+                    continue;
+                }
+                if state.window_open && state.window_open_name == "palette" {
+                    palette::palette_input(runtime, state, code);
+                    continue;
+                }
+                if state.window_open && state.window_open_name == "layers" {
+                    layers::layers_input(runtime, state, code);
+                    continue;
+                }
+                if state.window_open && state.window_open_name == "timeline" {
+                    timeline::timeline_input(runtime, state, code);
+                    continue;
+                }
+                if state.window_open && state.window_open_name == "import" {
+                    import::import_input(canvas, runtime, state, file_menu, code);
+                    continue;
+                }
+                if state.window_open && state.window_open_name == "export" {
+                    export::export_input(canvas, runtime, state, file_menu, code);
+                    continue;
+                }
+                if state.window_open && state.window_open_name == "ansi" {
+                    ansi::ansi_input(canvas, runtime, state, file_menu, code);
+                    continue;
+                }
+                if state.window_open && state.window_open_name == "vga" {
+                    vga::vga_input(runtime, state, file_menu, code);
+                    continue;
+                }
+
+                input::key_from_code(code)
+            }
+            Input::Mouse {
+                action,
+                column,
+                row,
+            } => {
+                mouse_input(canvas, runtime, state, file_menu, action, (column, row));
+                continue;
+            }
+            Input::Resize(..) | Input::Tick | Input::None => continue,
+        };
+
+        /* keyboard ends a mouse stroke in case release was missed */
+        runtime.history.close_groups();
 
         match key {
             KEY::W => {
-                remove_old_cursor(&mut stdout, runtime);
                 runtime.cursor_y -= 1.0;
 
-                place_new_cursor(&mut stdout, canvas, runtime);
+                place_new_cursor(canvas, runtime);
+                selection::follow_cursor(runtime);
                 runtime.last_pressed_key = KEY::W;
             }
             KEY::S => {
-                remove_old_cursor(&mut stdout, runtime);
                 runtime.cursor_y += 1.0;
 
-                place_new_cursor(&mut stdout, canvas, runtime);
+                place_new_cursor(canvas, runtime);
+                selection::follow_cursor(runtime);
                 runtime.last_pressed_key = KEY::S;
             }
             KEY::A => {
-                remove_old_cursor(&mut stdout, runtime);
                 runtime.cursor_x -= 1.0;
 
-                place_new_cursor(&mut stdout, canvas, runtime);
+                place_new_cursor(canvas, runtime);
+                selection::follow_cursor(runtime);
                 runtime.last_pressed_key = KEY::D;
             }
             KEY::D => {
-                remove_old_cursor(&mut stdout, runtime);
                 runtime.cursor_x += 1.0;
 
-                place_new_cursor(&mut stdout, canvas, runtime);
+                place_new_cursor(canvas, runtime);
+                selection::follow_cursor(runtime);
                 runtime.last_pressed_key = KEY::A;
             }
-            KEY::FILE => window::file_window(&mut stdout, canvas, runtime, state),
-            KEY::HELP => window::help_window(&mut stdout, canvas, runtime, state),
+            KEY::FILE => window::file_window(state),
+            KEY::HELP => window::help_window(state),
             KEY::PLACE => {
-                if (state.window_open == true && state.window_open_name == "file") {
-                    window::file_window_actions(&mut stdout, canvas, runtime, state, file_menu);
+                let cursor = (runtime.cursor_x as u32, runtime.cursor_y as u32);
+                if (state.window_open && state.window_open_name == "file") {
+                    if let Some(entry) = runtime
+                        .viewport
+                        .to_screen(cursor)
+                        .and_then(|(x, y)| window::file_window_entry(&runtime.viewport, x, y))
+                    {
+                        dialog::open_dialog(state, file_menu, entry);
+                    }
+                } else if runtime.floating.is_some() {
+                    selection::stamp(canvas, runtime);
+                } else if runtime.selection.as_ref().is_some_and(|s| s.marking) {
+                    selection::mark(runtime);
+                } else if runtime.shape.is_some() {
+                    finish_shape(canvas, runtime, true);
                 } else {
-                    place_blok(&mut stdout, runtime);
+                    place_blok(runtime);
 
                     move_cursor_blkey(runtime);
-                    place_new_cursor(&mut stdout, canvas, runtime);
+                    place_new_cursor(canvas, runtime);
                 }
             }
             KEY::ERASE => {
-                let current_color = runtime.color;
-                runtime.color = style::Color::White;
-
-                place_blok(&mut stdout, runtime);
-                runtime.color = current_color;
+                erase_blok(runtime);
 
                 move_cursor_blkey(runtime);
-                place_new_cursor(&mut stdout, canvas, runtime);
+                place_new_cursor(canvas, runtime);
             }
+            KEY::FILL => {
+                let start = (runtime.cursor_x as u32, runtime.cursor_y as u32);
+                let blocks: Vec<((u32, u32), Color)> = tools::flood_fill(
+                    canvas,
+                    runtime.layers.blocks(),
+                    start,
+                    runtime.color,
+                    runtime.fill_diagonal,
+                )
+                .into_iter()
+                .map(|position| (position, runtime.color))
+                .collect();
+
+                place_blocks(runtime, blocks);
+            }
+            KEY::NEIGHBOURS => runtime.fill_diagonal = !runtime.fill_diagonal,
+            KEY::LINE => start_shape(runtime, tools::ShapeKind::Line),
+            KEY::RECTANGLE => start_shape(runtime, tools::ShapeKind::Rectangle),
+            KEY::ELLIPSE => start_shape(runtime, tools::ShapeKind::Ellipse),
+            KEY::UNDO => apply_history(runtime, true),
+            KEY::REDO => apply_history(runtime, false),
+            KEY::MARK => selection::mark(runtime),
+            KEY::COPY => selection::copy(runtime),
+            KEY::CUT => selection::cut(runtime),
+            KEY::PASTE => selection::paste(runtime),
+            KEY::MOVE => selection::lift(runtime),
+            KEY::JUMP => window::jump_window(state),
+            KEY::PALETTE => palette::palette_window(runtime, state),
+            KEY::LAYERS => layers::layers_window(runtime, state),
+            KEY::TIMELINE => timeline::timeline_window(state),
+            KEY::PREVFRAME => runtime.layers.turn_frame(false),
+            KEY::NEXTFRAME => runtime.layers.turn_frame(true),
+            KEY::PREVPAGE => palette::turn_page(runtime, false),
+            KEY::NEXTPAGE => palette::turn_page(runtime, true),
+            KEY::ZOOMIN => zoom(canvas, runtime, true),
+            KEY::ZOOMOUT => zoom(canvas, runtime, false),
             KEY::QUIT => {
-                close(&mut stdout, canvas, runtime, state);
+                if runtime.floating.is_some() {
+                    selection::cancel_floating(runtime);
+                } else if runtime.selection.is_some() {
+                    runtime.selection = None;
+                } else if runtime.shape.is_some() {
+                    finish_shape(canvas, runtime, false);
+                } else if close(state) {
+                    return;
+                }
             }
             _ => {
                 if (key.clone() as u32 >= KEY::C1 as u32 && key.clone() as u32 <= KEY::C0 as u32) {
-                    runtime.color = return_color(key);
+                    palette::pick_slot(runtime, (key as u32 - KEY::C1 as u32) as usize);
                 }
             }
         }
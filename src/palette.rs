@@ -0,0 +1,468 @@
+use crossterm::{event::KeyCode, style};
+
+use crate::paint;
+use crate::render;
+use crate::variables;
+use crate::viewport;
+use crate::window;
+
+/// Width of palette window, border included
+const WIDTH: u16 = 49;
+
+/// Height of palette window, border included
+const HEIGHT: u16 = 16;
+
+/// Number of colors kept in "recent colors" row
+const RECENT_COLORS: usize = 10;
+
+/// Number of colors on one page of loaded palette, one for each of keys 1 - 0
+pub const PAGE_SIZE: usize = 10;
+
+/// State of palette window: typed color, why it couldn't be used and recently picked colors.
+/// `colors` is the loaded palette, keys 1 - 0 pick from its `page`
+pub struct Palette {
+    pub input: String,
+    pub error: Option<String>,
+    pub recent: Vec<style::Color>,
+    pub colors: Vec<style::Color>,
+    pub page: usize,
+}
+
+/// The 10 colors GVPaint always had, loaded until a palette file is opened
+pub fn default_colors() -> Vec<style::Color> {
+    let keys = [
+        paint::KEY::C1,
+        paint::KEY::C2,
+        paint::KEY::C3,
+        paint::KEY::C4,
+        paint::KEY::C5,
+        paint::KEY::C6,
+        paint::KEY::C7,
+        paint::KEY::C8,
+        paint::KEY::C9,
+        paint::KEY::C0,
+    ];
+
+    keys.into_iter().map(paint::return_color).collect()
+}
+
+impl Palette {
+    /// Number of pages of loaded palette
+    pub fn pages(&self) -> usize {
+        self.colors.len().div_ceil(PAGE_SIZE).max(1)
+    }
+
+    /// Colors picked by keys 1 - 0 on current page
+    pub fn page_colors(&self) -> &[style::Color] {
+        let start = (self.page * PAGE_SIZE).min(self.colors.len());
+        let end = (start + PAGE_SIZE).min(self.colors.len());
+        &self.colors[start..end]
+    }
+}
+
+/// Where a color can be picked in palette window, relative to its top left corner.
+/// ANSI colors are two cells wide, others take one cell
+enum Swatch {
+    Ansi(u8),
+    Color256(u8),
+    Recent(usize),
+    Slot(usize),
+}
+
+/// Convert one of 16 ANSI colors to crossterm's named color
+pub fn ansi_color(index: u8) -> style::Color {
+    match index {
+        0 => style::Color::Black,
+        1 => style::Color::DarkRed,
+        2 => style::Color::DarkGreen,
+        3 => style::Color::DarkYellow,
+        4 => style::Color::DarkBlue,
+        5 => style::Color::DarkMagenta,
+        6 => style::Color::DarkCyan,
+        7 => style::Color::Grey,
+        8 => style::Color::DarkGrey,
+        9 => style::Color::Red,
+        10 => style::Color::Green,
+        11 => style::Color::Yellow,
+        12 => style::Color::Blue,
+        13 => style::Color::Magenta,
+        14 => style::Color::Cyan,
+        _ => style::Color::White,
+    }
+}
+
+/// Convert a color of 256-color palette, first 16 are ANSI colors,
+/// then 6x6x6 color cube and 24 shades of grey as RGB
+pub fn color_256(index: u8) -> style::Color {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+    match index {
+        0..=15 => ansi_color(index),
+        16..=231 => {
+            let i = index - 16;
+            style::Color::Rgb {
+                r: LEVELS[(i / 36) as usize],
+                g: LEVELS[(i / 6 % 6) as usize],
+                b: LEVELS[(i % 6) as usize],
+            }
+        }
+        _ => {
+            let grey = 8 + (index - 232) * 10;
+            style::Color::Rgb {
+                r: grey,
+                g: grey,
+                b: grey,
+            }
+        }
+    }
+}
+
+/// Convert HSV (hue 0 - 360, saturation and value 0 - 100) to RGB
+pub fn hsv_to_rgb(h: f64, s: f64, v: f64) -> (u8, u8, u8) {
+    let s = s / 100.0;
+    let v = v / 100.0;
+    let c = v * s;
+    let h = (h % 360.0) / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+
+    let m = v - c;
+    let channel = |value: f64| ((value + m) * 255.0).round() as u8;
+
+    (channel(r), channel(g), channel(b))
+}
+
+/// Parse a color typed in palette window: `#rrggbb`, `r,g,b`, `hsv h,s,v`
+/// or a number of 256-color palette
+fn parse_color(text: &str) -> Result<style::Color, String> {
+    let text = text.trim().to_lowercase();
+
+    if let Some(hex) = text.strip_prefix('#') {
+        let value = u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|_| hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+            .ok_or(format!("`#{}` isn't a color, use #rrggbb", hex))?;
+
+        return Ok(style::Color::Rgb {
+            r: (value >> 16) as u8,
+            g: (value >> 8) as u8,
+            b: value as u8,
+        });
+    }
+
+    let (hsv, numbers) = match text.strip_prefix("hsv") {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix("rgb").unwrap_or(&text)),
+    };
+
+    let numbers: Vec<f64> = numbers
+        .split(|c: char| c == ',' || c == '(' || c == ')' || c.is_whitespace())
+        .filter(|field| !field.is_empty())
+        .map(|field| field.parse::<f64>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("`{}` isn't a color", text))?;
+
+    match numbers[..] {
+        [index] if !hsv && (0.0..=255.0).contains(&index) => Ok(color_256(index as u8)),
+        [h, s, v] if hsv => {
+            if !(0.0..=360.0).contains(&h)
+                || !(0.0..=100.0).contains(&s)
+                || !(0.0..=100.0).contains(&v)
+            {
+                return Err("HSV is hue 0 - 360, saturation and value 0 - 100".to_string());
+            }
+
+            let (r, g, b) = hsv_to_rgb(h, s, v);
+            Ok(style::Color::Rgb { r, g, b })
+        }
+        [r, g, b] if !hsv => {
+            if [r, g, b].iter().any(|c| !(0.0..=255.0).contains(c)) {
+                return Err("RGB channels are 0 - 255".to_string());
+            }
+
+            Ok(style::Color::Rgb {
+                r: r as u8,
+                g: g as u8,
+                b: b as u8,
+            })
+        }
+        _ => Err(format!("`{}` isn't a color", text)),
+    }
+}
+
+/// Use a color for next blocks and remember it in "recent colors" row
+pub fn pick(runtime: &mut variables::Runtime, color: style::Color) {
+    runtime.color = color;
+
+    let recent = &mut runtime.palette.recent;
+    recent.retain(|c| *c != color);
+    recent.insert(0, color);
+    recent.truncate(RECENT_COLORS);
+}
+
+/// Pick color of given slot (0 for key 1, 9 for key 0) on current page, missing slots do nothing
+pub fn pick_slot(runtime: &mut variables::Runtime, slot: usize) {
+    if let Some(color) = runtime.palette.page_colors().get(slot).copied() {
+        runtime.color = color;
+    }
+}
+
+/// Show next or previous page of loaded palette, it wraps around
+pub fn turn_page(runtime: &mut variables::Runtime, next: bool) {
+    let palette = &mut runtime.palette;
+    let pages = palette.pages();
+
+    palette.page = if next {
+        (palette.page + 1) % pages
+    } else {
+        (palette.page + pages - 1) % pages
+    };
+}
+
+/// Replace loaded palette, first page is shown
+pub fn load(runtime: &mut variables::Runtime, colors: Vec<style::Color>) {
+    runtime.palette.colors = colors;
+    runtime.palette.page = 0;
+}
+
+/// Open or close palette window
+pub fn palette_window(runtime: &mut variables::Runtime, state: &mut variables::State) {
+    runtime.palette.input.clear();
+    runtime.palette.error = None;
+    window::toggle_window(state, "palette");
+}
+
+/// Position of window's top left corner
+fn origin(viewport: &viewport::Viewport) -> (u16, u16) {
+    (4, viewport.height.saturating_sub(HEIGHT + 2).max(1))
+}
+
+/// Draw a color swatch
+fn swatch(renderer: &mut render::Renderer, x: u16, y: u16, width: u16, color: style::Color) {
+    for i in 0..width {
+        renderer.set(
+            (x + i) as i64,
+            y as i64,
+            render::Cell {
+                ch: render::BLOCK,
+                fg: color,
+                bg: style::Color::White,
+            },
+        );
+    }
+}
+
+/// Draw palette window: ANSI colors, color cube, shades of grey,
+/// recent colors and typed color
+pub fn draw_palette(
+    renderer: &mut render::Renderer,
+    viewport: &viewport::Viewport,
+    runtime: &variables::Runtime,
+) {
+    let (x, y) = origin(viewport);
+    let palette = &runtime.palette;
+    window::draw_border(renderer, x, y, WIDTH, HEIGHT);
+
+    window::print_lines(
+        renderer,
+        x + 2,
+        y + 1,
+        &["Palette - click a color, Esc - close"],
+        style::Color::Red,
+    );
+
+    for i in 0..16 {
+        swatch(renderer, x + 2 + i * 2, y + 2, 2, ansi_color(i as u8));
+    }
+
+    /* cube has red going down, green in groups of six across and blue inside them */
+    for i in 16..=231u16 {
+        let cube = i - 16;
+        swatch(
+            renderer,
+            x + 2 + cube % 36,
+            y + 3 + cube / 36,
+            1,
+            color_256(i as u8),
+        );
+    }
+    for i in 232..=255u16 {
+        swatch(renderer, x + 2 + (i - 232), y + 9, 1, color_256(i as u8));
+    }
+
+    window::print_lines(renderer, x + 2, y + 10, &["Recent:"], style::Color::Red);
+    for (i, color) in palette.recent.iter().enumerate() {
+        swatch(renderer, x + 10 + i as u16 * 3, y + 10, 2, *color);
+    }
+
+    window::print_lines(renderer, x + 2, y + 11, &["Keys:"], style::Color::Red);
+    for (i, color) in palette.page_colors().iter().enumerate() {
+        swatch(renderer, x + 10 + i as u16 * 3, y + 11, 2, *color);
+    }
+    window::print_lines(
+        renderer,
+        x + 40,
+        y + 11,
+        &[&format!("{}/{}", palette.page + 1, palette.pages())],
+        style::Color::Red,
+    );
+
+    window::print_lines(renderer, x + 2, y + 12, &["Current:"], style::Color::Red);
+    swatch(renderer, x + 11, y + 12, 2, runtime.color);
+
+    window::print_lines(
+        renderer,
+        x + 2,
+        y + 13,
+        &[&format!("Color: {}_", palette.input)],
+        style::Color::DarkYellow,
+    );
+
+    let hint = match &palette.error {
+        Some(error) => error.as_str(),
+        None => "#rrggbb, r,g,b, hsv h,s,v or 0 - 255",
+    };
+    let hint: String = hint.chars().take(WIDTH as usize - 4).collect();
+    window::print_lines(renderer, x + 2, y + 14, &[&hint], style::Color::DarkGreen);
+}
+
+/// Return swatch at given screen position
+fn swatch_at(
+    viewport: &viewport::Viewport,
+    runtime: &variables::Runtime,
+    column: u16,
+    row: u16,
+) -> Option<Swatch> {
+    let (x, y) = origin(viewport);
+    let column = column.checked_sub(x + 2)?;
+    let row = row.checked_sub(y)?;
+
+    match row {
+        2 if column < 32 => Some(Swatch::Ansi((column / 2) as u8)),
+        3..=8 if column < 36 => Some(Swatch::Color256((16 + (row - 3) * 36 + column) as u8)),
+        9 if column < 24 => Some(Swatch::Color256((232 + column) as u8)),
+        10 if column >= 8 && (column - 8) % 3 < 2 => {
+            let i = ((column - 8) / 3) as usize;
+            (i < runtime.palette.recent.len()).then_some(Swatch::Recent(i))
+        }
+        11 if column >= 8 && (column - 8) % 3 < 2 => {
+            let i = ((column - 8) / 3) as usize;
+            (i < runtime.palette.page_colors().len()).then_some(Swatch::Slot(i))
+        }
+        _ => None,
+    }
+}
+
+/// Pick a color clicked in palette window, window is closed then
+pub fn palette_click(
+    runtime: &mut variables::Runtime,
+    state: &mut variables::State,
+    column: u16,
+    row: u16,
+) {
+    let color = match swatch_at(&runtime.viewport, runtime, column, row) {
+        Some(Swatch::Ansi(i)) => ansi_color(i),
+        Some(Swatch::Color256(i)) => color_256(i),
+        Some(Swatch::Recent(i)) => runtime.palette.recent[i],
+        Some(Swatch::Slot(i)) => runtime.palette.page_colors()[i],
+        None => return,
+    };
+
+    pick(runtime, color);
+    state.window_open = false;
+}
+
+/// Handle a key pressed while palette window is open, Enter picks typed color
+pub fn palette_input(
+    runtime: &mut variables::Runtime,
+    state: &mut variables::State,
+    code: KeyCode,
+) {
+    let palette = &mut runtime.palette;
+
+    match code {
+        KeyCode::Esc => state.window_open = false,
+        KeyCode::Enter => match parse_color(&palette.input) {
+            Ok(color) => {
+                pick(runtime, color);
+                state.window_open = false;
+            }
+            Err(error) => palette.error = Some(error),
+        },
+        KeyCode::Backspace => {
+            palette.input.pop();
+            palette.error = None;
+        }
+        KeyCode::Char(c) if c.is_ascii_alphanumeric() || "#,() ".contains(c) => {
+            palette.input.push(c);
+            palette.error = None;
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(r: u8, g: u8, b: u8) -> style::Color {
+        style::Color::Rgb { r, g, b }
+    }
+
+    #[test]
+    fn hsv_primaries_and_greys() {
+        assert_eq!(hsv_to_rgb(0.0, 100.0, 100.0), (255, 0, 0));
+        assert_eq!(hsv_to_rgb(120.0, 100.0, 100.0), (0, 255, 0));
+        assert_eq!(hsv_to_rgb(240.0, 100.0, 100.0), (0, 0, 255));
+        assert_eq!(hsv_to_rgb(360.0, 100.0, 100.0), (255, 0, 0));
+        assert_eq!(hsv_to_rgb(30.0, 100.0, 100.0), (255, 128, 0));
+        assert_eq!(hsv_to_rgb(200.0, 0.0, 50.0), (128, 128, 128));
+        assert_eq!(hsv_to_rgb(0.0, 0.0, 0.0), (0, 0, 0));
+    }
+
+    #[test]
+    fn valid_colors() {
+        assert_eq!(parse_color("#FF8800"), Ok(rgb(255, 136, 0)));
+        assert_eq!(parse_color(" 255, 136, 0 "), Ok(rgb(255, 136, 0)));
+        assert_eq!(parse_color("1 2 3"), Ok(rgb(1, 2, 3)));
+        assert_eq!(parse_color("196"), Ok(color_256(196)));
+        assert_eq!(parse_color("9"), Ok(style::Color::Red));
+    }
+
+    #[test]
+    fn named_forms() {
+        assert_eq!(parse_color("rgb(255, 136, 0)"), Ok(rgb(255, 136, 0)));
+        assert_eq!(parse_color("HSV 120,100,100"), Ok(rgb(0, 255, 0)));
+        assert_eq!(parse_color("hsv(240, 100, 100)"), Ok(rgb(0, 0, 255)));
+    }
+
+    #[test]
+    fn invalid_colors() {
+        for text in [
+            "#+12345",
+            "#-12345",
+            "#12345",
+            "#1234567",
+            "#gg0000",
+            "",
+            "red",
+            "256",
+            "1, 2",
+            "1, 2, 300",
+            "hsv 400, 0, 0",
+            "hsv 0, 101, 0",
+            "hsv 1",
+            "rgb 1, x, 3",
+        ] {
+            assert!(parse_color(text).is_err(), "`{}` should be rejected", text);
+        }
+    }
+}
@@ -0,0 +1,561 @@
+use std::io::{self, Stdout, Write};
+
+use crossterm::{cursor, style, terminal, QueueableCommand};
+
+use crate::ansi;
+use crate::dialog;
+use crate::export;
+use crate::import;
+use crate::layers;
+use crate::palette;
+use crate::selection;
+use crate::timeline;
+use crate::tools;
+use crate::variables;
+use crate::vga;
+use crate::viewport::{Viewport, Zoom};
+use crate::window;
+
+/// Full block, every placed block is drawn with it
+pub const BLOCK: char = '\u{2588}';
+
+/// One terminal cell: character with its foreground and background color
+#[derive(Clone, Copy, PartialEq)]
+pub struct Cell {
+    pub ch: char,
+    pub fg: style::Color,
+    pub bg: style::Color,
+}
+
+/// Empty cell, canvas background is white
+pub const EMPTY: Cell = Cell {
+    ch: ' ',
+    fg: style::Color::White,
+    bg: style::Color::White,
+};
+
+/// Terminal outside of canvas, left in its default colors
+pub const OUTSIDE: Cell = Cell {
+    ch: ' ',
+    fg: style::Color::Reset,
+    bg: style::Color::Reset,
+};
+
+/// Two frame buffers: everything is drawn into the back one, front one holds
+/// what is on screen. Flush writes only cells which differ between them.
+/// Renderer is as large as the terminal, canvas can be smaller
+pub struct Renderer {
+    pub width: u16,
+    pub height: u16,
+    back: Vec<Cell>,
+    front: Vec<Option<Cell>>,
+    clear_screen: bool,
+}
+
+impl Renderer {
+    pub fn new(width: u16, height: u16) -> Renderer {
+        let size = width as usize * height as usize;
+
+        Renderer {
+            width,
+            height,
+            back: vec![OUTSIDE; size],
+            front: vec![None; size],
+            clear_screen: true,
+        }
+    }
+
+    /// Change size after terminal was resized, whole screen is cleared and repainted
+    pub fn resize(&mut self, width: u16, height: u16) {
+        *self = Renderer::new(width, height);
+    }
+
+    /// Forget what's on screen, next flush repaints every cell
+    pub fn invalidate(&mut self) {
+        self.front.iter_mut().for_each(|cell| *cell = None);
+    }
+
+    /// Fill back buffer with given cell
+    pub fn clear(&mut self, cell: Cell) {
+        self.back.iter_mut().for_each(|c| *c = cell);
+    }
+
+    /// Set a cell, positions outside of screen are ignored
+    pub fn set(&mut self, x: i64, y: i64, cell: Cell) {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return;
+        }
+
+        self.back[y as usize * self.width as usize + x as usize] = cell;
+    }
+
+    /// Return a cell from back buffer
+    pub fn get(&self, x: i64, y: i64) -> Option<Cell> {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return None;
+        }
+
+        Some(self.back[y as usize * self.width as usize + x as usize])
+    }
+
+    /// Draw a block of given color, `None` is an empty cell
+    pub fn block(&mut self, x: i64, y: i64, color: Option<style::Color>) {
+        match color {
+            Some(color) => self.set(
+                x,
+                y,
+                Cell {
+                    ch: BLOCK,
+                    fg: color,
+                    bg: style::Color::White,
+                },
+            ),
+            None => self.set(x, y, EMPTY),
+        }
+    }
+
+    /// Write text on white background
+    pub fn text(&mut self, x: u16, y: u16, text: &str, color: style::Color) {
+        for (i, ch) in text.chars().enumerate() {
+            self.set(
+                x as i64 + i as i64,
+                y as i64,
+                Cell {
+                    ch,
+                    fg: color,
+                    bg: style::Color::White,
+                },
+            );
+        }
+    }
+
+    /// Write changed cells in one batch. Cursor is moved only when changed
+    /// cells aren't next to each other and colors are set only when they change
+    pub fn flush(&mut self, stdout: &mut Stdout) -> io::Result<()> {
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut position: Option<(u16, u16)> = None;
+        let mut fg: Option<style::Color> = None;
+        let mut bg: Option<style::Color> = None;
+
+        if self.clear_screen {
+            /* terminal might have moved old content around while resizing */
+            buffer.queue(style::SetBackgroundColor(style::Color::Reset))?;
+            buffer.queue(terminal::Clear(terminal::ClearType::All))?;
+            self.clear_screen = false;
+        }
+
+        for (i, cell) in self.back.iter().enumerate() {
+            if self.front[i] == Some(*cell) {
+                continue;
+            }
+
+            let x = (i % self.width as usize) as u16;
+            let y = (i / self.width as usize) as u16;
+
+            if position != Some((x, y)) {
+                buffer.queue(cursor::MoveTo(x, y))?;
+            }
+            if fg != Some(cell.fg) {
+                buffer.queue(style::SetForegroundColor(cell.fg))?;
+                fg = Some(cell.fg);
+            }
+            if bg != Some(cell.bg) {
+                buffer.queue(style::SetBackgroundColor(cell.bg))?;
+                bg = Some(cell.bg);
+            }
+
+            write!(buffer, "{}", cell.ch)?;
+            self.front[i] = Some(*cell);
+
+            /* last column would wrap, so position is unknown after it */
+            position = if x + 1 < self.width {
+                Some((x + 1, y))
+            } else {
+                None
+            };
+        }
+
+        if !buffer.is_empty() {
+            stdout.write_all(&buffer)?;
+            stdout.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Canvas pixel after tools are drawn over the painting.
+/// `None` color is an empty cell, marked pixels are part of selection's outline
+#[derive(Clone, Copy)]
+struct Pixel {
+    color: Option<style::Color>,
+    marked: bool,
+}
+
+/// Visible part of canvas, pixels outside of canvas are `None`
+struct Pixels {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    pixels: Vec<Option<Pixel>>,
+}
+
+impl Pixels {
+    fn get(&self, position: (u32, u32)) -> Option<Pixel> {
+        let index = self.index(position)?;
+        self.pixels[index]
+    }
+
+    fn get_mut(&mut self, position: (u32, u32)) -> Option<&mut Pixel> {
+        let index = self.index(position)?;
+        self.pixels[index].as_mut()
+    }
+
+    fn index(&self, position: (u32, u32)) -> Option<usize> {
+        if position.0 < self.x
+            || position.1 < self.y
+            || position.0 >= self.x + self.width
+            || position.1 >= self.y + self.height
+        {
+            return None;
+        }
+
+        Some(((position.1 - self.y) * self.width + position.0 - self.x) as usize)
+    }
+}
+
+/// Draw painting and tool previews into pixels which are visible
+fn draw_pixels(
+    canvas: &variables::Canvas,
+    runtime: &variables::Runtime,
+    viewport: &Viewport,
+) -> Pixels {
+    let (width, height) = viewport.span();
+    let mut pixels = Pixels {
+        x: viewport.x,
+        y: viewport.y,
+        width,
+        height,
+        pixels: Vec::with_capacity((width * height) as usize),
+    };
+
+    for y in viewport.y..viewport.y + height {
+        for x in viewport.x..viewport.x + width {
+            let inside = tools::in_canvas(canvas, (x as i64, y as i64));
+            pixels.pixels.push(inside.then_some(Pixel {
+                color: None,
+                marked: false,
+            }));
+        }
+    }
+
+    /* blocks are sorted by X, so only visible columns are visited.
+    Blocks being moved are shown only at cursor */
+    let hidden = match &runtime.floating {
+        Some(floating) => floating.hidden(&runtime.layers),
+        None => &[],
+    };
+    let columns = viewport.x..viewport.x + width;
+    for (k, v) in &runtime.layers.onion_skin(columns.clone()) {
+        if let Some(pixel) = pixels.get_mut(*k) {
+            pixel.color = Some(*v);
+        }
+    }
+    for (k, v) in &runtime
+        .layers
+        .composite(runtime.layers.frame, columns, hidden)
+    {
+        if let Some(pixel) = pixels.get_mut(*k) {
+            pixel.color = Some(*v);
+        }
+    }
+
+    let cursor = (runtime.cursor_x as u32, runtime.cursor_y as u32);
+
+    if let Some(shape) = &runtime.shape {
+        for position in tools::rasterize(canvas, shape, cursor) {
+            if let Some(pixel) = pixels.get_mut(position) {
+                pixel.color = Some(runtime.color);
+            }
+        }
+    }
+
+    if let Some(selection) = &runtime.selection {
+        for position in selection::outline(canvas, selection, cursor) {
+            if let Some(pixel) = pixels.get_mut(position) {
+                pixel.marked = true;
+            }
+        }
+    }
+
+    if let Some(floating) = &runtime.floating {
+        for (position, color) in selection::floating_blocks(canvas, runtime, floating) {
+            if let Some(pixel) = pixels.get_mut(position) {
+                pixel.color = Some(color);
+            }
+        }
+    }
+
+    pixels
+}
+
+/// Cell showing one pixel of canvas
+fn pixel_cell(pixel: Pixel) -> Cell {
+    if pixel.marked {
+        /* color stays visible as background */
+        return Cell {
+            ch: '\u{2592}',
+            fg: style::Color::DarkGrey,
+            bg: pixel.color.unwrap_or(style::Color::White),
+        };
+    }
+
+    match pixel.color {
+        Some(color) => Cell {
+            ch: BLOCK,
+            fg: color,
+            bg: style::Color::White,
+        },
+        None => EMPTY,
+    }
+}
+
+/// Cell showing a group of pixels when zoomed out. Every placed pixel is a dot
+/// and dots take the most common color, cell can't show more than one
+fn group_cell(zoom: Zoom, group: &[Option<Pixel>]) -> Cell {
+    let mut bits = 0;
+    let mut colors: Vec<(style::Color, usize)> = Vec::new();
+    let mut marked = false;
+
+    for (i, pixel) in group.iter().enumerate() {
+        let Some(pixel) = pixel else {
+            continue;
+        };
+
+        marked |= pixel.marked;
+        if let Some(color) = pixel.color {
+            bits |= 1 << i;
+            match colors.iter_mut().find(|(c, _)| *c == color) {
+                Some((_, count)) => *count += 1,
+                None => colors.push((color, 1)),
+            }
+        }
+    }
+
+    let color = colors
+        .iter()
+        .max_by_key(|(_, count)| *count)
+        .map(|(color, _)| *color);
+
+    if marked {
+        return pixel_cell(Pixel {
+            color,
+            marked: true,
+        });
+    }
+
+    Cell {
+        ch: zoom.glyph(bits),
+        fg: color.unwrap_or(style::Color::White),
+        bg: style::Color::White,
+    }
+}
+
+/// Color of one half of a half block, marked pixels are grey
+fn half_color(pixel: Option<Pixel>) -> style::Color {
+    match pixel {
+        None => style::Color::Reset,
+        Some(Pixel { marked: true, .. }) => style::Color::DarkGrey,
+        Some(Pixel { color, .. }) => color.unwrap_or(style::Color::White),
+    }
+}
+
+/// Cell showing two pixels above each other, top one is foreground of `▀`
+/// and bottom one its background. Cursor is given with its color and half it's in
+fn half_block_cell(
+    top: Option<Pixel>,
+    bottom: Option<Pixel>,
+    cursor: Option<(style::Color, bool)>,
+) -> Cell {
+    let mut fg = half_color(top);
+    let mut bg = half_color(bottom);
+
+    match cursor {
+        Some((color, true)) => fg = color,
+        Some((color, false)) => bg = color,
+        None => {}
+    }
+
+    Cell {
+        ch: '\u{2580}',
+        fg,
+        bg,
+    }
+}
+
+/// Draw whole frame into back buffer: painting, tool previews, cursor and open window
+pub fn draw_frame(
+    renderer: &mut Renderer,
+    canvas: &variables::Canvas,
+    runtime: &variables::Runtime,
+    state: &variables::State,
+    file_menu: &variables::FileMenu,
+) {
+    let viewport = &runtime.viewport;
+    renderer.clear(OUTSIDE);
+
+    if window::too_small(viewport) {
+        draw_too_small(renderer);
+        return;
+    }
+
+    let pixels = draw_pixels(canvas, runtime, viewport);
+    let (pixel_width, pixel_height) = viewport.zoom.pixels_per_cell();
+    let (cell_width, cell_height) = viewport.zoom.cells_per_pixel();
+    let cursor = (runtime.cursor_x as u32, runtime.cursor_y as u32);
+
+    /* one group is a pixel drawn over more cells, or more pixels drawn in one cell */
+    for group_y in 0..pixels.height / pixel_height {
+        for group_x in 0..pixels.width / pixel_width {
+            let from = (
+                pixels.x + group_x * pixel_width,
+                pixels.y + group_y * pixel_height,
+            );
+
+            let mut group = Vec::new();
+            for y in 0..pixel_height {
+                for x in 0..pixel_width {
+                    group.push(pixels.get((from.0 + x, from.1 + y)));
+                }
+            }
+            if group.iter().all(Option::is_none) {
+                /* cells past the edge of canvas keep terminal's colors */
+                continue;
+            }
+
+            let under_cursor = (from.0..from.0 + pixel_width).contains(&cursor.0)
+                && (from.1..from.1 + pixel_height).contains(&cursor.1);
+
+            let cell = if viewport.zoom == Zoom::HalfBlock {
+                /* cursor covers only its own half, so picture stays as it's exported */
+                let top_cursor = under_cursor && cursor.1 == from.1;
+                half_block_cell(
+                    group[0],
+                    group[1],
+                    under_cursor.then_some((runtime.cursor_color, top_cursor)),
+                )
+            } else if under_cursor {
+                Cell {
+                    ch: BLOCK,
+                    fg: runtime.cursor_color,
+                    bg: style::Color::White,
+                }
+            } else if group.len() == 1 {
+                pixel_cell(group[0].unwrap())
+            } else {
+                group_cell(viewport.zoom, &group)
+            };
+
+            for y in 0..cell_height {
+                for x in 0..cell_width {
+                    renderer.set(
+                        (group_x * cell_width + x) as i64,
+                        (group_y * cell_height + y + 1) as i64,
+                        cell,
+                    );
+                }
+            }
+        }
+    }
+
+    renderer.text(0, 0, "Press 'H' or 'h' for help!", style::Color::Red);
+    renderer.text(30, 0, &layers::status(runtime), style::Color::DarkGreen);
+
+    draw_position(renderer, canvas, viewport, cursor);
+
+    if state.window_open {
+        match state.window_open_name.as_str() {
+            "help" => window::draw_help_window(renderer, viewport),
+            "file" => window::draw_file_window(renderer, viewport),
+            "message" => window::draw_message_box(renderer, viewport, state),
+            "dialog" => dialog::draw_dialog(renderer, viewport, file_menu),
+            "jump" => window::draw_jump_window(renderer, viewport, state),
+            "palette" => palette::draw_palette(renderer, viewport, runtime),
+            "layers" => layers::draw_layers_window(renderer, viewport, runtime),
+            "timeline" => timeline::draw_timeline_window(renderer, viewport, runtime),
+            "import" => import::draw_import_window(renderer, viewport, file_menu),
+            "export" => export::draw_export_window(renderer, viewport, runtime, file_menu),
+            "ansi" => ansi::draw_ansi_window(renderer, viewport, file_menu),
+            "vga" => vga::draw_vga_window(renderer, viewport, file_menu),
+            _ => {}
+        }
+    }
+}
+
+/// Draw position indicator on the last row: cursor, canvas size and visible part of it
+fn draw_position(
+    renderer: &mut Renderer,
+    canvas: &variables::Canvas,
+    viewport: &Viewport,
+    cursor: (u32, u32),
+) {
+    let row = viewport.height.saturating_sub(1);
+    let span = viewport.span();
+    let last = (
+        (viewport.x + span.0).min(canvas.width as u32),
+        (viewport.y + span.1).min(canvas.height as u32),
+    );
+
+    let text = format!(
+        " X: {}  Y: {} | canvas {}x{} | view {}-{}, {}-{} | zoom {} | J - jump",
+        cursor.0,
+        cursor.1,
+        canvas.width,
+        canvas.height,
+        viewport.x,
+        last.0.saturating_sub(1),
+        viewport.y,
+        last.1.saturating_sub(1),
+        viewport.zoom.label(),
+    );
+
+    renderer.text(
+        0,
+        row,
+        &" ".repeat(viewport.width as usize),
+        style::Color::White,
+    );
+    renderer.text(0, row, &text, style::Color::DarkGrey);
+}
+
+/// Screen shown instead of canvas when terminal is too small for windows
+fn draw_too_small(renderer: &mut Renderer) {
+    let lines = [
+        "Terminal too small".to_string(),
+        format!(
+            "{}x{}, need at least {}x{}",
+            renderer.width,
+            renderer.height,
+            window::MIN_WIDTH,
+            window::MIN_HEIGHT
+        ),
+        "Resize it or press Q to exit".to_string(),
+    ];
+
+    let top = (renderer.height as i64 - lines.len() as i64) / 2;
+    for (i, line) in lines.iter().enumerate() {
+        let x = (renderer.width as i64 - line.chars().count() as i64).max(0) / 2;
+        for (j, ch) in line.chars().enumerate() {
+            renderer.set(
+                x + j as i64,
+                top + i as i64,
+                Cell {
+                    ch,
+                    fg: style::Color::Reset,
+                    bg: style::Color::Reset,
+                },
+            );
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use crossterm::style;
+
+use crate::history;
+use crate::layers;
+use crate::paint;
+use crate::tools;
+use crate::variables;
+
+/// Marked rectangle on canvas, both corners are inside of it.
+/// While marking, the second corner follows the cursor
+pub struct Selection {
+    pub anchor: (u32, u32),
+    pub end: (u32, u32),
+    pub marking: bool,
+}
+
+impl Selection {
+    /// Top left and bottom right corner
+    pub fn bounds(&self) -> ((u32, u32), (u32, u32)) {
+        (
+            (self.anchor.0.min(self.end.0), self.anchor.1.min(self.end.1)),
+            (self.anchor.0.max(self.end.0), self.anchor.1.max(self.end.1)),
+        )
+    }
+
+    fn contains(&self, position: &(u32, u32)) -> bool {
+        let (from, to) = self.bounds();
+        position.0 >= from.0 && position.0 <= to.0 && position.1 >= from.1 && position.1 <= to.1
+    }
+}
+
+/// Cells a moved selection was lifted from, in layer and frame with given ids
+pub struct Source {
+    pub layer: u32,
+    pub frame: u32,
+    pub cells: Vec<(u32, u32)>,
+}
+
+/// Blocks floating at cursor until they are stamped. When selection is moved,
+/// `source` cells are cleared once it's stamped, even if another layer or frame is active then
+pub struct Floating {
+    pub blocks: Vec<((u32, u32), style::Color)>,
+    pub source: Option<Source>,
+}
+
+impl Floating {
+    /// Cells of active layer in current frame which are shown only at cursor
+    pub fn hidden(&self, layers: &layers::Layers) -> &[(u32, u32)] {
+        match &self.source {
+            Some(source)
+                if source.layer == layers.active().id && source.frame == layers.frame_id() =>
+            {
+                &source.cells
+            }
+            _ => &[],
+        }
+    }
+}
+
+/// Start marking a selection at cursor, or finish the one being marked
+pub fn mark(runtime: &mut variables::Runtime) {
+    if let Some(selection) = runtime.selection.as_mut() {
+        if selection.marking {
+            selection.marking = false;
+            return;
+        }
+    }
+
+    let cursor = (runtime.cursor_x as u32, runtime.cursor_y as u32);
+    runtime.selection = Some(Selection {
+        anchor: cursor,
+        end: cursor,
+        marking: true,
+    });
+}
+
+/// Move the second corner of selection being marked to cursor
+pub fn follow_cursor(runtime: &mut variables::Runtime) {
+    let cursor = (runtime.cursor_x as u32, runtime.cursor_y as u32);
+
+    if let Some(selection) = runtime.selection.as_mut() {
+        if selection.marking {
+            selection.end = cursor;
+        }
+    }
+}
+
+/// Cells of selection outline
+pub fn outline(
+    canvas: &variables::Canvas,
+    selection: &Selection,
+    cursor: (u32, u32),
+) -> Vec<(u32, u32)> {
+    let end = if selection.marking {
+        cursor
+    } else {
+        selection.end
+    };
+    let from = (selection.anchor.0.min(end.0), selection.anchor.1.min(end.1));
+    let to = (selection.anchor.0.max(end.0), selection.anchor.1.max(end.1));
+
+    tools::rasterize(
+        canvas,
+        &tools::Shape {
+            kind: tools::ShapeKind::Rectangle,
+            anchor: from,
+            filled: false,
+        },
+        to,
+    )
+}
+
+/// Copy blocks of active layer inside selection to clipboard, empty cells stay transparent
+pub fn copy(runtime: &mut variables::Runtime) {
+    let Some(selection) = runtime.selection.as_ref() else {
+        return;
+    };
+
+    let (from, _) = selection.bounds();
+    runtime.clipboard = runtime
+        .layers
+        .blocks()
+        .iter()
+        .filter(|(position, _)| selection.contains(position))
+        .map(|(position, color)| ((position.0 - from.0, position.1 - from.1), *color))
+        .collect();
+}
+
+/// Copy selection to clipboard and erase it from canvas as one undo step
+pub fn cut(runtime: &mut variables::Runtime) {
+    copy(runtime);
+
+    let Some(selection) = runtime.selection.as_ref() else {
+        return;
+    };
+
+    let cells: Vec<(u32, u32)> = runtime
+        .layers
+        .blocks()
+        .keys()
+        .filter(|position| selection.contains(position))
+        .copied()
+        .collect();
+
+    erase_cells(runtime, &cells);
+}
+
+/// Erase given cells as one undo step, cursor stays where it was
+fn erase_cells(runtime: &mut variables::Runtime, cells: &[(u32, u32)]) {
+    let current_x = runtime.cursor_x;
+    let current_y = runtime.cursor_y;
+
+    runtime.history.begin_group();
+    for position in cells {
+        runtime.cursor_x = position.0 as f64;
+        runtime.cursor_y = position.1 as f64;
+
+        paint::erase_blok(runtime);
+    }
+    runtime.history.end_group();
+
+    runtime.cursor_x = current_x;
+    runtime.cursor_y = current_y;
+}
+
+/// Start floating clipboard content at cursor
+pub fn paste(runtime: &mut variables::Runtime) {
+    if runtime.clipboard.is_empty() {
+        return;
+    }
+
+    runtime.floating = Some(Floating {
+        blocks: runtime.clipboard.clone(),
+        source: None,
+    });
+}
+
+/// Lift selection from canvas and let it float at cursor, nothing changes until it's stamped
+pub fn lift(runtime: &mut variables::Runtime) {
+    let Some(selection) = runtime.selection.take() else {
+        return;
+    };
+
+    let (from, _) = selection.bounds();
+    let mut blocks = Vec::new();
+    let mut source = Source {
+        layer: runtime.layers.active().id,
+        frame: runtime.layers.frame_id(),
+        cells: Vec::new(),
+    };
+
+    for (position, color) in runtime.layers.blocks() {
+        if selection.contains(position) {
+            blocks.push(((position.0 - from.0, position.1 - from.1), *color));
+            source.cells.push(*position);
+        }
+    }
+
+    runtime.floating = Some(Floating {
+        blocks,
+        source: Some(source),
+    });
+}
+
+/// Positions where floating blocks would be stamped, blocks outside canvas are dropped
+pub fn floating_blocks(
+    canvas: &variables::Canvas,
+    runtime: &variables::Runtime,
+    floating: &Floating,
+) -> Vec<((u32, u32), style::Color)> {
+    let cursor = (runtime.cursor_x as i64, runtime.cursor_y as i64);
+
+    floating
+        .blocks
+        .iter()
+        .map(|(offset, color)| ((cursor.0 + offset.0 as i64, cursor.1 + offset.1 as i64), *color))
+        .filter(|(position, _)| tools::in_canvas(canvas, *position))
+        .map(|(position, color)| ((position.0 as u32, position.1 as u32), color))
+        .collect()
+}
+
+/// Stamp floating blocks at cursor as one undo step, moved selection is cleared first.
+/// Blocks keep floating while active layer is locked
+pub fn stamp(canvas: &variables::Canvas, runtime: &mut variables::Runtime) {
+    if runtime.layers.active().locked {
+        return;
+    }
+    let Some(floating) = runtime.floating.take() else {
+        return;
+    };
+
+    let blocks = floating_blocks(canvas, runtime, &floating);
+
+    runtime.history.begin_group();
+    if let Some(source) = &floating.source {
+        clear_source(runtime, source);
+    }
+    paint::place_blocks(runtime, blocks);
+    runtime.history.end_group();
+}
+
+/// Clear cells moved selection was lifted from, in the layer and frame it was lifted in
+fn clear_source(runtime: &mut variables::Runtime, source: &Source) {
+    let Some(blocks) = runtime
+        .layers
+        .by_id_mut(source.layer)
+        .filter(|layer| !layer.locked)
+        .and_then(|layer| layer.cels.get_mut(&source.frame))
+    else {
+        return;
+    };
+
+    for position in &source.cells {
+        let before = blocks.remove(position);
+        runtime.history.record(history::Change::Cell {
+            layer: source.layer,
+            frame: source.frame,
+            position: *position,
+            before,
+            after: None,
+        });
+    }
+}
+
+/// Throw floating blocks away, moved selection stays where it was
+pub fn cancel_floating(runtime: &mut variables::Runtime) {
+    runtime.floating = None;
+}
@@ -0,0 +1,205 @@
+use std::time::Duration;
+
+use crossterm::{event::KeyCode, style};
+
+use crate::file;
+use crate::layers;
+use crate::paint;
+use crate::render;
+use crate::variables;
+use crate::viewport;
+use crate::window;
+
+/// Width of timeline window, border included
+const WIDTH: u16 = 49;
+
+/// Height of timeline window, border included
+const HEIGHT: u16 = 11;
+
+/// Number of frames listed in timeline window at once
+const VISIBLE_FRAMES: usize = 10;
+
+/// Slowest and fastest playback
+const MIN_FPS: u32 = 1;
+const MAX_FPS: u32 = 60;
+
+/// Open or close timeline window
+pub fn timeline_window(state: &mut variables::State) {
+    window::toggle_window(state, "timeline");
+}
+
+/// Position of window's top left corner
+fn origin(viewport: &viewport::Viewport) -> (u16, u16) {
+    (4, viewport.height.saturating_sub(HEIGHT + 2).max(1))
+}
+
+/// Draw timeline window, current frame is in brackets
+pub fn draw_timeline_window(
+    renderer: &mut render::Renderer,
+    viewport: &viewport::Viewport,
+    runtime: &variables::Runtime,
+) {
+    let (x, y) = origin(viewport);
+    let layers = &runtime.layers;
+    window::draw_border(renderer, x, y, WIDTH, HEIGHT);
+
+    window::print_lines(
+        renderer,
+        x + 2,
+        y + 1,
+        &["Timeline - Esc - close"],
+        style::Color::Red,
+    );
+
+    let start = layers.frame / VISIBLE_FRAMES * VISIBLE_FRAMES;
+    let end = (start + VISIBLE_FRAMES).min(layers.frames.len());
+    let mut strip = String::from(if start > 0 { "< " } else { "  " });
+    for i in start..end {
+        if i == layers.frame {
+            strip += &format!("[{}]", i + 1);
+        } else {
+            strip += &format!(" {} ", i + 1);
+        }
+    }
+    if end < layers.frames.len() {
+        strip += " >";
+    }
+
+    window::print_lines(
+        renderer,
+        x + 2,
+        y + 3,
+        &[
+            &strip,
+            &format!(
+                "Frame {} of {}, {} FPS, onion skin {}",
+                layers.frame + 1,
+                layers.frames.len(),
+                layers.fps,
+                if layers.onion { "on" } else { "off" }
+            ),
+        ],
+        style::Color::DarkYellow,
+    );
+
+    window::print_lines(
+        renderer,
+        x + 2,
+        y + 6,
+        &[
+            "N - new, C - copy, X - delete frame",
+            "A / D - previous / next frame",
+            "O - onion skin, + / - FPS, P - play",
+        ],
+        style::Color::DarkGreen,
+    );
+}
+
+/// Handle a key pressed while timeline window is open
+pub fn timeline_input(
+    runtime: &mut variables::Runtime,
+    state: &mut variables::State,
+    code: KeyCode,
+) {
+    let layers = &mut runtime.layers;
+
+    match code {
+        KeyCode::Esc => state.window_open = false,
+        KeyCode::Left => layers.turn_frame(false),
+        KeyCode::Right => layers.turn_frame(true),
+        KeyCode::Char(c) => match c.to_ascii_lowercase() {
+            'n' => runtime.history.record(layers.add_frame(false)),
+            'c' => runtime.history.record(layers.add_frame(true)),
+            'x' => runtime
+                .history
+                .record_step(layers.delete_frame().into_iter().collect()),
+            'a' | ',' => layers.turn_frame(false),
+            'd' | '.' => layers.turn_frame(true),
+            'o' => layers.onion = !layers.onion,
+            '+' | '=' => layers.fps = (layers.fps + 1).min(MAX_FPS),
+            '-' | '_' => layers.fps = layers.fps.saturating_sub(1).max(MIN_FPS),
+            'p' => {
+                state.window_open = false;
+                play(runtime);
+            }
+            'q' | 't' => state.window_open = false,
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+/// Handle a mouse click while timeline window is open, clicked frame becomes current
+pub fn timeline_click(runtime: &mut variables::Runtime, column: u16, row: u16) {
+    let (x, y) = origin(&runtime.viewport);
+    if row != y + 3 || column < x + 4 {
+        return;
+    }
+
+    let layers = &mut runtime.layers;
+    let start = layers.frame / VISIBLE_FRAMES * VISIBLE_FRAMES;
+    let end = (start + VISIBLE_FRAMES).min(layers.frames.len());
+
+    /* strip starts after "< ", every frame number has a space or bracket on both sides */
+    let mut left = x + 4;
+    for i in start..end {
+        let right = left + (i + 1).to_string().len() as u16 + 2;
+        if column < right {
+            layers.frame = i;
+            return;
+        }
+        left = right;
+    }
+}
+
+/// Start playing frames one after another, selection and shapes are dropped first
+pub fn play(runtime: &mut variables::Runtime) {
+    if runtime.layers.frames.len() < 2 {
+        return;
+    }
+
+    runtime.shape = None;
+    runtime.selection = None;
+    runtime.layers.playing = true;
+}
+
+/// How long to wait for input before next frame is shown, `None` when nothing is played
+pub fn frame_duration(runtime: &variables::Runtime) -> Option<Duration> {
+    let layers = &runtime.layers;
+    layers
+        .playing
+        .then(|| Duration::from_millis(1000 / layers.fps.max(MIN_FPS) as u64))
+}
+
+/// Put frames of opened file into active layer, starting at current frame.
+/// Missing frames are added at the end, all blocks are one undo step.
+/// Nothing is loaded into a locked layer
+pub fn load_frames(
+    runtime: &mut variables::Runtime,
+    state: &mut variables::State,
+    document: file::Document,
+) {
+    if layers::check_locked(runtime, state) {
+        return;
+    }
+
+    let start = runtime.layers.frame;
+    if document.frames.len() > 1 {
+        runtime.layers.fps = document.fps;
+    }
+
+    runtime.history.begin_group();
+    for (i, blocks) in document.frames.into_iter().enumerate() {
+        let layers = &mut runtime.layers;
+        if start + i >= layers.frames.len() {
+            layers.frame = layers.frames.len() - 1;
+            runtime.history.record(layers.add_frame(false));
+        }
+        layers.frame = start + i;
+
+        paint::place_blocks(runtime, blocks);
+    }
+    runtime.history.end_group();
+
+    runtime.layers.frame = start;
+}
@@ -0,0 +1,324 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crossterm::style;
+
+use crate::variables;
+
+/// Check if a position is inside canvas
+pub fn in_canvas(canvas: &variables::Canvas, position: (i64, i64)) -> bool {
+    position.0 >= 0
+        && position.1 >= 0
+        && position.0 < canvas.width as i64
+        && position.1 < canvas.height as i64
+}
+
+/// Return positions of a region connected to `start` which has same color as `start`.
+/// Empty cells count as white background, region never leaves the canvas
+pub fn flood_fill(
+    canvas: &variables::Canvas,
+    placed: &BTreeMap<(u32, u32), style::Color>,
+    start: (u32, u32),
+    color: style::Color,
+    diagonal: bool,
+) -> Vec<(u32, u32)> {
+    let color_at = |position: &(u32, u32)| {
+        placed
+            .get(position)
+            .copied()
+            .unwrap_or(style::Color::White)
+    };
+
+    let target = color_at(&start);
+    if target == color || !in_canvas(canvas, (start.0 as i64, start.1 as i64)) {
+        return Vec::new();
+    }
+
+    let mut neighbours: Vec<(i64, i64)> = vec![(0, -1), (0, 1), (-1, 0), (1, 0)];
+    if diagonal {
+        neighbours.extend([(-1, -1), (1, -1), (-1, 1), (1, 1)]);
+    }
+
+    let mut region: BTreeSet<(u32, u32)> = BTreeSet::new();
+    let mut stack: Vec<(u32, u32)> = vec![start];
+    region.insert(start);
+
+    while let Some((x, y)) = stack.pop() {
+        for (dx, dy) in &neighbours {
+            let next = (x as i64 + dx, y as i64 + dy);
+            if !in_canvas(canvas, next) {
+                continue;
+            }
+
+            let next = (next.0 as u32, next.1 as u32);
+            if color_at(&next) == target && region.insert(next) {
+                stack.push(next);
+            }
+        }
+    }
+
+    region.into_iter().collect()
+}
+
+/// Shapes which can be drawn with shape tools
+#[derive(Clone, Copy, PartialEq)]
+pub enum ShapeKind {
+    Line,
+    Rectangle,
+    Ellipse,
+}
+
+/// Shape being drawn, it's anchored at one point and follows the cursor
+/// until it's confirmed
+pub struct Shape {
+    pub kind: ShapeKind,
+    pub anchor: (u32, u32),
+    pub filled: bool,
+}
+
+/// Rasterize a line from `a` to `b` with Bresenham's algorithm
+fn line(a: (i64, i64), b: (i64, i64)) -> Vec<(i64, i64)> {
+    let dx = (b.0 - a.0).abs();
+    let dy = -(b.1 - a.1).abs();
+    let sx = if a.0 < b.0 { 1 } else { -1 };
+    let sy = if a.1 < b.1 { 1 } else { -1 };
+
+    let mut points = Vec::new();
+    let (mut x, mut y) = a;
+    let mut err = dx + dy;
+
+    loop {
+        points.push((x, y));
+        if x == b.0 && y == b.1 {
+            break;
+        }
+
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+
+    points
+}
+
+/// Rasterize a rectangle with corners at `a` and `b`
+fn rectangle(a: (i64, i64), b: (i64, i64), filled: bool) -> Vec<(i64, i64)> {
+    let (x0, x1) = (a.0.min(b.0), a.0.max(b.0));
+    let (y0, y1) = (a.1.min(b.1), a.1.max(b.1));
+
+    let mut points = Vec::new();
+    for y in y0..=y1 {
+        for x in x0..=x1 {
+            if filled || x == x0 || x == x1 || y == y0 || y == y1 {
+                points.push((x, y));
+            }
+        }
+    }
+
+    points
+}
+
+/// Rasterize an ellipse inscribed in a rectangle with corners at `a` and `b`,
+/// midpoint algorithm by Alois Zingl
+fn ellipse(a: (i64, i64), b: (i64, i64), filled: bool) -> Vec<(i64, i64)> {
+    let (mut x0, mut y0, mut x1, mut y1) = (a.0, a.1, b.0, b.1);
+
+    let mut w = (x1 - x0).abs();
+    let h = (y1 - y0).abs();
+    let mut b1 = h & 1;
+
+    let mut dx = 4 * (1 - w) * h * h;
+    let mut dy = 4 * (b1 + 1) * w * w;
+    let mut err = dx + dy + b1 * w * w;
+
+    if x0 > x1 {
+        x0 = x1;
+        x1 += w;
+    }
+    if y0 > y1 {
+        y0 = y1;
+    }
+    y0 += (h + 1) / 2;
+    y1 = y0 - b1;
+    w = 8 * w * w;
+    b1 = 8 * h * h;
+
+    let mut points = Vec::new();
+    loop {
+        points.extend([(x1, y0), (x0, y0), (x0, y1), (x1, y1)]);
+
+        let e2 = 2 * err;
+        if e2 <= dy {
+            y0 += 1;
+            y1 -= 1;
+            dy += w;
+            err += dy;
+        }
+        if e2 >= dx || 2 * err > dy {
+            x0 += 1;
+            x1 -= 1;
+            dx += b1;
+            err += dx;
+        }
+
+        if x0 > x1 {
+            break;
+        }
+    }
+
+    /* flat ellipses need their tips finished */
+    while y0 - y1 <= h {
+        points.extend([(x0 - 1, y0), (x1 + 1, y0), (x0 - 1, y1), (x1 + 1, y1)]);
+        y0 += 1;
+        y1 -= 1;
+    }
+
+    if filled {
+        /* outline has its leftmost and rightmost point in every row, fill between them */
+        let mut rows: BTreeMap<i64, (i64, i64)> = BTreeMap::new();
+        for (x, y) in &points {
+            let row = rows.entry(*y).or_insert((*x, *x));
+            row.0 = row.0.min(*x);
+            row.1 = row.1.max(*x);
+        }
+
+        points = rows
+            .into_iter()
+            .flat_map(|(y, (from, to))| (from..=to).map(move |x| (x, y)))
+            .collect();
+    }
+
+    points
+}
+
+/// Rasterize a shape from its anchor to `end`, only positions inside canvas are kept
+pub fn rasterize(canvas: &variables::Canvas, shape: &Shape, end: (u32, u32)) -> Vec<(u32, u32)> {
+    let a = (shape.anchor.0 as i64, shape.anchor.1 as i64);
+    let b = (end.0 as i64, end.1 as i64);
+
+    let points = match shape.kind {
+        ShapeKind::Line => line(a, b),
+        ShapeKind::Rectangle => rectangle(a, b, shape.filled),
+        ShapeKind::Ellipse => ellipse(a, b, shape.filled),
+    };
+
+    let unique: BTreeSet<(u32, u32)> = points
+        .into_iter()
+        .filter(|position| in_canvas(canvas, *position))
+        .map(|(x, y)| (x as u32, y as u32))
+        .collect();
+
+    unique.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CANVAS: variables::Canvas = variables::Canvas {
+        width: 5,
+        height: 4,
+    };
+
+    /// Vertical wall of red blocks in column 2
+    fn wall() -> BTreeMap<(u32, u32), style::Color> {
+        (0..4).map(|y| ((2, y), style::Color::Red)).collect()
+    }
+
+    #[test]
+    fn fill_stops_at_other_colors_and_canvas_border() {
+        let region = flood_fill(&CANVAS, &wall(), (0, 0), style::Color::Blue, false);
+
+        assert_eq!(region.len(), 8);
+        assert!(region.iter().all(|(x, y)| *x < 2 && *y < 4));
+    }
+
+    #[test]
+    fn fill_of_placed_color() {
+        let region = flood_fill(&CANVAS, &wall(), (2, 1), style::Color::Blue, false);
+
+        assert_eq!(region, vec![(2, 0), (2, 1), (2, 2), (2, 3)]);
+    }
+
+    #[test]
+    fn diagonal_fill_goes_through_corners() {
+        let placed: BTreeMap<(u32, u32), style::Color> =
+            [((1, 0), style::Color::Red), ((0, 1), style::Color::Red)].into();
+
+        let region = flood_fill(&CANVAS, &placed, (0, 0), style::Color::Blue, false);
+        assert_eq!(region, vec![(0, 0)]);
+
+        let region = flood_fill(&CANVAS, &placed, (1, 0), style::Color::Blue, true);
+        assert_eq!(region, vec![(0, 1), (1, 0)]);
+    }
+
+    #[test]
+    fn fill_with_same_color_or_outside_does_nothing() {
+        let placed = wall();
+
+        assert!(flood_fill(&CANVAS, &placed, (0, 0), style::Color::White, false).is_empty());
+        assert!(flood_fill(&CANVAS, &placed, (2, 0), style::Color::Red, false).is_empty());
+        assert!(flood_fill(&CANVAS, &placed, (9, 9), style::Color::Blue, false).is_empty());
+    }
+
+    fn shape(kind: ShapeKind, anchor: (u32, u32), filled: bool) -> Shape {
+        Shape {
+            kind,
+            anchor,
+            filled,
+        }
+    }
+
+    #[test]
+    fn line_in_both_directions() {
+        let forward = rasterize(&CANVAS, &shape(ShapeKind::Line, (0, 0), false), (4, 2));
+        let backward = rasterize(&CANVAS, &shape(ShapeKind::Line, (4, 2), false), (0, 0));
+
+        /* one block in every column, ends included */
+        for points in [forward, backward] {
+            assert_eq!(points.len(), 5);
+            assert!(points.contains(&(0, 0)) && points.contains(&(4, 2)));
+            assert!((0..5).all(|x| points.iter().any(|point| point.0 == x)));
+        }
+    }
+
+    #[test]
+    fn single_point_shapes() {
+        for kind in [ShapeKind::Line, ShapeKind::Rectangle, ShapeKind::Ellipse] {
+            assert_eq!(rasterize(&CANVAS, &shape(kind, (1, 1), false), (1, 1)), vec![(1, 1)]);
+        }
+    }
+
+    #[test]
+    fn rectangle_outline_and_filled() {
+        let outline = rasterize(&CANVAS, &shape(ShapeKind::Rectangle, (3, 2), false), (0, 0));
+        let filled = rasterize(&CANVAS, &shape(ShapeKind::Rectangle, (0, 0), true), (3, 2));
+
+        assert_eq!(outline.len(), 10);
+        assert!(!outline.contains(&(1, 1)));
+        assert_eq!(filled.len(), 12);
+    }
+
+    #[test]
+    fn ellipse_stays_in_its_bounds() {
+        let outline = rasterize(&CANVAS, &shape(ShapeKind::Ellipse, (0, 0), false), (4, 3));
+        let filled = rasterize(&CANVAS, &shape(ShapeKind::Ellipse, (0, 0), true), (4, 3));
+
+        assert!(outline.contains(&(0, 1)) && outline.contains(&(4, 1)));
+        assert!(outline.contains(&(2, 0)) && outline.contains(&(2, 3)));
+        assert!(!outline.contains(&(2, 1)) && filled.contains(&(2, 1)));
+        assert!(outline.iter().all(|position| filled.contains(position)));
+    }
+
+    #[test]
+    fn shapes_are_clipped_to_canvas() {
+        let points = rasterize(&CANVAS, &shape(ShapeKind::Line, (0, 0), false), (9, 0));
+
+        assert_eq!(points, vec![(0, 0), (1, 0), (2, 0), (3, 0), (4, 0)]);
+    }
+}
@@ -1,9 +1,21 @@
 use crossterm::style;
-use std::collections::BTreeMap;
+use std::path::PathBuf;
 
+use crate::ansi::AnsiOptions;
+use crate::dialog::FileDialog;
+use crate::export::ExportOptions;
+use crate::history::History;
+use crate::import::ImportOptions;
+use crate::layers::Layers;
 use crate::paint::KEY;
+use crate::palette::Palette;
+use crate::vga::VgaOptions;
+use crate::viewport::Viewport;
+use crate::selection::{Floating, Selection};
+use crate::tools::Shape;
 
-/// Struct holding information about canvas's width and height
+/// Struct holding information about canvas's width and height,
+/// it doesn't depend on terminal's size
 pub struct Canvas {
     pub width: u16,
     pub height: u16,
@@ -14,23 +26,58 @@ pub struct Canvas {
 /// cursor's color
 /// color for next block,
 /// last pressed key (1 - 4, W, S, A, D),
-/// placed blocks
+/// layers with placed blocks,
+/// undo/redo history,
+/// whether bucket fill goes diagonally too (8-connected),
+/// shape being drawn with shape tools,
+/// marked selection, clipboard and blocks floating at cursor,
+/// part of canvas shown on screen,
+/// palette window with recently picked colors
 pub struct Runtime {
     pub cursor_x: f64,
     pub cursor_y: f64,
     pub cursor_color: style::Color,
     pub color: style::Color,
     pub last_pressed_key: KEY,
-    pub placed: BTreeMap<(u32, u32), style::Color>,
+    pub layers: Layers,
+    pub history: History,
+    pub fill_diagonal: bool,
+    pub shape: Option<Shape>,
+    pub selection: Option<Selection>,
+    pub clipboard: Vec<((u32, u32), style::Color)>,
+    pub floating: Option<Floating>,
+    pub viewport: Viewport,
+    pub palette: Palette,
 }
 
-/// Struct which holds runtime information about windows
+/// Struct which holds runtime information about windows,
+/// title and text of shown message box and position typed in jump window
 pub struct State {
     pub window_open: bool,
     pub window_open_name: String,
+    pub message: Option<(String, String)>,
+    pub jump: String,
 }
 
-/// Struct holding temporary information required for file menu
+/// Struct holding temporary information required for file menu,
+/// open file dialog and path used last time in this session
 pub struct FileMenu {
-    pub file_content: String,
+    pub dialog: Option<FileDialog>,
+    pub last_path: Option<PathBuf>,
+    /// .png file waiting in import window
+    pub import: Option<PathBuf>,
+    pub import_options: ImportOptions,
+    /// .png file waiting in export window
+    pub export: Option<PathBuf>,
+    /// Every frame is exported to an animation, not only the current one
+    pub export_frames: bool,
+    pub export_options: ExportOptions,
+    /// ANSI art file waiting in its window
+    pub ansi: Option<PathBuf>,
+    /// ANSI art file is opened, not saved
+    pub ansi_open: bool,
+    pub ansi_options: AnsiOptions,
+    /// VGA text buffer waiting in its export window
+    pub vga: Option<PathBuf>,
+    pub vga_options: VgaOptions,
 }
@@ -0,0 +1,484 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crossterm::{event::KeyCode, style};
+
+use crate::file::{self, FileError};
+use crate::paint;
+use crate::render;
+use crate::variables;
+use crate::viewport;
+use crate::window;
+
+/// Width of VGA export window, border included
+const WIDTH: u16 = 49;
+
+/// Height of VGA export window, border included
+const HEIGHT: u16 = 11;
+
+/// Text modes export window cycles through, columns and rows
+const SIZES: [(u32, u32); 4] = [(80, 25), (80, 43), (80, 50), (40, 25)];
+
+/// Extensions of exported buffers, the first one is used when none is typed
+pub const EXTENSIONS: &[&str] = &["bin", "asm", "inc", "h", "c"];
+
+/// Characters of code page 437 blocks are drawn with
+const FULL_BLOCK: u8 = 0xdb;
+const LOWER_HALF: u8 = 0xdc;
+const UPPER_HALF: u8 = 0xdf;
+
+/// Color of empty cells, white like the canvas
+const WHITE: u8 = 0x0f;
+
+/// Empty cell: white block, so it doesn't need a bright background
+const EMPTY: (u8, u8) = (FULL_BLOCK, WHITE);
+
+/// RGB of 16 VGA text mode colors, index is the attribute value
+const VGA_PALETTE: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0xaa],
+    [0x00, 0xaa, 0x00],
+    [0x00, 0xaa, 0xaa],
+    [0xaa, 0x00, 0x00],
+    [0xaa, 0x00, 0xaa],
+    [0xaa, 0x55, 0x00],
+    [0xaa, 0xaa, 0xaa],
+    [0x55, 0x55, 0x55],
+    [0x55, 0x55, 0xff],
+    [0x55, 0xff, 0x55],
+    [0x55, 0xff, 0xff],
+    [0xff, 0x55, 0x55],
+    [0xff, 0x55, 0xff],
+    [0xff, 0xff, 0x55],
+    [0xff, 0xff, 0xff],
+];
+
+/// How buffer is written, chosen by file extension
+#[derive(Clone, Copy, PartialEq)]
+pub enum VgaFormat {
+    /// Bytes exactly as they are at 0xB8000
+    Raw,
+    /// NASM include with `db` lines
+    Nasm,
+    /// C array
+    C,
+}
+
+/// Options of VGA export, they stay the same until GVPaint is closed
+pub struct VgaOptions {
+    /// Columns and rows of text mode
+    pub size: (u32, u32),
+    /// Two blocks in a cell with upper and lower half block characters
+    pub half_blocks: bool,
+    /// Backgrounds can use all 16 colors, blink bit has to be turned off by the kernel
+    pub bright_background: bool,
+}
+
+/// Format of a file, chosen by extension
+pub fn vga_format(path: &Path) -> Option<VgaFormat> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+
+    match extension.as_str() {
+        "bin" => Some(VgaFormat::Raw),
+        "asm" | "inc" => Some(VgaFormat::Nasm),
+        "h" | "c" => Some(VgaFormat::C),
+        _ => None,
+    }
+}
+
+/// VGA color of a block. Colors of keys 1 - 0 are the ones PekOS-GV used,
+/// others get the nearest of 16 VGA colors
+pub fn vga_color(color: style::Color) -> u8 {
+    if let Some(bios) = paint::return_bios_color(color) {
+        return bios as u8;
+    }
+
+    let [r, g, b] = file::crossterm_to_image_color(color).0.map(|c| c as i32);
+    let distance = |index: &usize| {
+        let [vr, vg, vb] = VGA_PALETTE[*index].map(|c| c as i32);
+        (vr - r).pow(2) + (vg - g).pow(2) + (vb - b).pow(2)
+    };
+
+    (0..VGA_PALETTE.len())
+        .min_by_key(distance)
+        .unwrap_or_default() as u8
+}
+
+/// Character and attribute of a cell with two blocks, top one and bottom one.
+/// Without bright backgrounds the dark color goes to background if there is one
+fn half_block_cell(top: u8, bottom: u8, bright_background: bool) -> (u8, u8) {
+    if top == bottom {
+        return (FULL_BLOCK, top);
+    }
+
+    /* blink bit is on, bright colors can't be in background */
+    if !bright_background && bottom >= 8 {
+        if top < 8 {
+            return (LOWER_HALF, (top << 4) | bottom);
+        }
+        return (UPPER_HALF, ((bottom & 0x07) << 4) | top);
+    }
+
+    (UPPER_HALF, (bottom << 4) | top)
+}
+
+/// Character and attribute byte of every cell, row by row from top left corner of canvas.
+/// Blocks which don't fit on screen are left out, empty cells are white like the canvas
+pub fn painting_to_cells(
+    placed: &BTreeMap<(u32, u32), style::Color>,
+    options: &VgaOptions,
+) -> Vec<(u8, u8)> {
+    let (columns, rows) = options.size;
+    let block = |x: u32, y: u32| placed.get(&(x, y)).map(|color| vga_color(*color));
+    let mut cells = Vec::with_capacity((columns * rows) as usize);
+
+    for row in 0..rows {
+        for x in 0..columns {
+            let cell = if options.half_blocks {
+                let top = block(x, row * 2).unwrap_or(WHITE);
+                let bottom = block(x, row * 2 + 1).unwrap_or(WHITE);
+                half_block_cell(top, bottom, options.bright_background)
+            } else {
+                match block(x, row) {
+                    Some(color) => (FULL_BLOCK, color),
+                    None => EMPTY,
+                }
+            };
+            cells.push(cell);
+        }
+    }
+
+    cells
+}
+
+/// Name of labels and arrays, made from file name so more drawings can be included
+fn symbol_name(path: &Path) -> String {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name: String = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        name.insert(0, '_');
+    }
+    name
+}
+
+/// Bytes of a row as hexadecimal numbers, 16 on a line
+fn hex_lines(cells: &[(u8, u8)]) -> Vec<String> {
+    let bytes: Vec<String> = cells
+        .iter()
+        .flat_map(|(character, attribute)| [*character, *attribute])
+        .map(|b| format!("0x{:02x}", b))
+        .collect();
+
+    bytes.chunks(16).map(|chunk| chunk.join(", ")).collect()
+}
+
+/// NASM include, one `db` line for every 8 cells
+fn cells_to_nasm(cells: &[(u8, u8)], (columns, rows): (u32, u32), name: &str) -> String {
+    let mut text = format!(
+        "; {}x{} VGA text mode buffer made with GVPaint, copy it to 0xB8000\n\
+         ; every cell is a character and an attribute (background << 4 | foreground)\n\
+         {name}_width equ {}\n\
+         {name}_height equ {}\n\n\
+         {name}:\n",
+        columns, rows, columns, rows
+    );
+
+    for (row, cells) in cells.chunks(columns as usize).enumerate() {
+        text += &format!("    ; row {}\n", row);
+        for line in hex_lines(cells) {
+            text += &format!("    db {}\n", line);
+        }
+    }
+
+    text + &format!("{name}_size equ $ - {name}\n")
+}
+
+/// C array, rows are marked with comments
+fn cells_to_c(cells: &[(u8, u8)], (columns, rows): (u32, u32), name: &str) -> String {
+    let upper = name.to_ascii_uppercase();
+    let mut text = format!(
+        "/* {}x{} VGA text mode buffer made with GVPaint, copy it to 0xB8000 */\n\
+         /* every cell is a character and an attribute (background << 4 | foreground) */\n\
+         #define {upper}_WIDTH {}\n\
+         #define {upper}_HEIGHT {}\n\n\
+         static const unsigned char {name}[{upper}_WIDTH * {upper}_HEIGHT * 2] = {{\n",
+        columns, rows, columns, rows
+    );
+
+    for (row, cells) in cells.chunks(columns as usize).enumerate() {
+        text += &format!("    /* row {} */\n", row);
+        for line in hex_lines(cells) {
+            text += &format!("    {},\n", line);
+        }
+    }
+
+    text + "};\n"
+}
+
+/// Save a painting to VGA text mode buffer, format is chosen by extension
+pub fn save_vga(
+    path: &Path,
+    placed: &BTreeMap<(u32, u32), style::Color>,
+    options: &VgaOptions,
+) -> Result<(), FileError> {
+    let cells = painting_to_cells(placed, options);
+    let name = symbol_name(path);
+
+    let content = match vga_format(path).unwrap_or(VgaFormat::Raw) {
+        VgaFormat::Raw => cells
+            .iter()
+            .flat_map(|(character, attribute)| [*character, *attribute])
+            .collect(),
+        VgaFormat::Nasm => cells_to_nasm(&cells, options.size, &name).into_bytes(),
+        VgaFormat::C => cells_to_c(&cells, options.size, &name).into_bytes(),
+    };
+
+    fs::write(path, content).map_err(|e| FileError::Write(path.into(), e))
+}
+
+/// Ask for text mode and characters before VGA buffer is saved
+pub fn vga_window(state: &mut variables::State, file_menu: &mut variables::FileMenu, path: &Path) {
+    file_menu.vga = Some(PathBuf::from(path));
+    state.window_open = true;
+    state.window_open_name = "vga".to_string();
+}
+
+/// Position of window's top left corner
+fn origin(viewport: &viewport::Viewport) -> (u16, u16) {
+    (4, viewport.height.saturating_sub(HEIGHT + 2).max(1))
+}
+
+/// Draw VGA export window with current options
+pub fn draw_vga_window(
+    renderer: &mut render::Renderer,
+    viewport: &viewport::Viewport,
+    file_menu: &variables::FileMenu,
+) {
+    let Some(path) = file_menu.vga.as_ref() else {
+        return;
+    };
+
+    let (x, y) = origin(viewport);
+    let options = &file_menu.vga_options;
+    window::draw_border(renderer, x, y, WIDTH, HEIGHT);
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let title: String = format!("Save VGA buffer: {}", name)
+        .chars()
+        .take(WIDTH as usize - 4)
+        .collect();
+    window::print_lines(renderer, x + 2, y + 1, &[&title], style::Color::Red);
+
+    let size = format!(
+        "1 - text mode: {}x{} characters",
+        options.size.0, options.size.1
+    );
+    let characters = if options.half_blocks {
+        "2 - characters: ▀ ▄, two blocks in a cell"
+    } else {
+        "2 - characters: █, a block in every cell"
+    };
+    let background = if options.bright_background {
+        "3 - backgrounds: 16 colors, no blinking"
+    } else {
+        "3 - backgrounds: 8 colors, blink bit off"
+    };
+    let format = match vga_format(path).unwrap_or(VgaFormat::Raw) {
+        VgaFormat::Raw => "Raw dump of 0xB8000 (.bin)",
+        VgaFormat::Nasm => "NASM include with db lines (.asm, .inc)",
+        VgaFormat::C => "C array (.h, .c)",
+    };
+
+    window::print_lines(
+        renderer,
+        x + 2,
+        y + 3,
+        &[&size, characters, background],
+        style::Color::DarkYellow,
+    );
+    window::print_lines(renderer, x + 2, y + 7, &[format], style::Color::DarkYellow);
+    window::print_lines(
+        renderer,
+        x + 2,
+        y + HEIGHT - 2,
+        &["1 - 3 - change, Enter - save, Esc - cancel"],
+        style::Color::DarkGreen,
+    );
+}
+
+/// Change an option, 0 is the first line of options
+fn toggle_option(options: &mut VgaOptions, line: u16) {
+    match line {
+        0 => {
+            let i = SIZES.iter().position(|s| *s == options.size).unwrap_or(0);
+            options.size = SIZES[(i + 1) % SIZES.len()];
+        }
+        1 => options.half_blocks = !options.half_blocks,
+        2 => options.bright_background = !options.bright_background,
+        _ => {}
+    }
+}
+
+/// Save current frame to VGA buffer waiting in export window
+fn save(
+    runtime: &variables::Runtime,
+    state: &mut variables::State,
+    file_menu: &mut variables::FileMenu,
+) {
+    state.window_open = false;
+    let Some(path) = file_menu.vga.take() else {
+        return;
+    };
+
+    let result = save_vga(
+        &path,
+        &runtime.layers.flatten(runtime.layers.frame),
+        &file_menu.vga_options,
+    );
+
+    if let Err(e) = result {
+        window::message_box(state, "Error", &e.to_string());
+    }
+}
+
+/// Handle a key pressed while VGA export window is open
+pub fn vga_input(
+    runtime: &variables::Runtime,
+    state: &mut variables::State,
+    file_menu: &mut variables::FileMenu,
+    code: KeyCode,
+) {
+    match code {
+        KeyCode::Esc => {
+            file_menu.vga = None;
+            state.window_open = false;
+        }
+        KeyCode::Enter => save(runtime, state, file_menu),
+        KeyCode::Char(c @ '1'..='3') => {
+            toggle_option(&mut file_menu.vga_options, c as u16 - '1' as u16)
+        }
+        _ => {}
+    }
+}
+
+/// Handle a mouse click while VGA export window is open, clicked option is changed
+pub fn vga_click(
+    viewport: &viewport::Viewport,
+    file_menu: &mut variables::FileMenu,
+    column: u16,
+    row: u16,
+) {
+    let (x, y) = origin(viewport);
+    if column <= x || column >= x + WIDTH - 1 || row < y + 3 {
+        return;
+    }
+
+    toggle_option(&mut file_menu.vga_options, row - y - 3);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(size: (u32, u32), half_blocks: bool, bright_background: bool) -> VgaOptions {
+        VgaOptions {
+            size,
+            half_blocks,
+            bright_background,
+        }
+    }
+
+    #[test]
+    fn full_blocks() {
+        let placed: BTreeMap<(u32, u32), style::Color> = [
+            ((0, 0), style::Color::Red),
+            ((2, 1), style::Color::Blue),
+            ((3, 0), style::Color::Rgb { r: 0, g: 0, b: 160 }),
+            ((9, 0), style::Color::Red),
+        ]
+        .into();
+        let cells = painting_to_cells(&placed, &options((4, 2), false, false));
+
+        assert_eq!(
+            cells,
+            vec![
+                (0xdb, 0x0c),
+                EMPTY,
+                EMPTY,
+                (0xdb, 0x01),
+                EMPTY,
+                EMPTY,
+                (0xdb, 0x01),
+                EMPTY
+            ]
+        );
+    }
+
+    #[test]
+    fn half_blocks_without_bright_backgrounds() {
+        let placed: BTreeMap<(u32, u32), style::Color> = [
+            /* red over blue, dark one goes to background */
+            ((0, 0), style::Color::Red),
+            ((0, 1), style::Color::Blue),
+            /* black over red, bright one has to be in foreground */
+            ((1, 0), style::Color::Black),
+            ((1, 1), style::Color::Red),
+            /* yellow over red, red can only be dark in background */
+            ((2, 0), style::Color::Yellow),
+            ((2, 1), style::Color::Red),
+            /* yellow over empty white */
+            ((3, 0), style::Color::Yellow),
+        ]
+        .into();
+        let cells = painting_to_cells(&placed, &options((5, 1), true, false));
+
+        assert_eq!(
+            cells,
+            vec![
+                (0xdf, 0x1c),
+                (0xdc, 0x0c),
+                (0xdf, 0x4e),
+                (0xdf, 0x7e),
+                EMPTY
+            ]
+        );
+    }
+
+    #[test]
+    fn half_blocks_with_bright_backgrounds() {
+        let placed: BTreeMap<(u32, u32), style::Color> = [
+            ((0, 0), style::Color::Yellow),
+            ((0, 1), style::Color::Red),
+            ((1, 0), style::Color::Black),
+            ((1, 1), style::Color::Red),
+            ((2, 0), style::Color::Red),
+            ((2, 1), style::Color::Red),
+            ((3, 1), style::Color::Blue),
+        ]
+        .into();
+        let cells = painting_to_cells(&placed, &options((4, 1), true, true));
+
+        assert_eq!(
+            cells,
+            vec![(0xdf, 0xce), (0xdf, 0xc0), (0xdb, 0x0c), (0xdf, 0x1f)]
+        );
+    }
+
+    #[test]
+    fn format_and_symbol_name() {
+        assert!(vga_format(Path::new("logo.BIN")) == Some(VgaFormat::Raw));
+        assert!(vga_format(Path::new("logo.inc")) == Some(VgaFormat::Nasm));
+        assert!(vga_format(Path::new("logo.h")) == Some(VgaFormat::C));
+        assert!(vga_format(Path::new("logo.txt")).is_none());
+
+        assert_eq!(symbol_name(Path::new("my-logo 2.asm")), "my_logo_2");
+        assert_eq!(symbol_name(Path::new("1st.h")), "_1st");
+    }
+}
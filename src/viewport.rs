@@ -0,0 +1,231 @@
+use crate::variables;
+
+/// How canvas pixels are shown in terminal cells, from the farthest to the closest
+#[derive(Clone, Copy, PartialEq)]
+pub enum Zoom {
+    /// Overview, 2x4 pixels in one cell drawn with Braille dots
+    Braille,
+    /// Overview, 2x3 pixels in one cell drawn with sextants
+    Sextant,
+    /// Two pixels above each other in one cell drawn with `▀`, pixels are square
+    HalfBlock,
+    /// One pixel is one cell
+    Normal,
+    /// One pixel is 2x1 cells, close to square
+    Wide,
+    /// One pixel is 4x2 cells
+    Large,
+}
+
+/// Zoom levels in order, `+` and `-` move through them
+const ZOOM_LEVELS: [Zoom; 6] = [
+    Zoom::Braille,
+    Zoom::Sextant,
+    Zoom::HalfBlock,
+    Zoom::Normal,
+    Zoom::Wide,
+    Zoom::Large,
+];
+
+impl Zoom {
+    /// Number of pixels drawn in one cell, across and down
+    pub fn pixels_per_cell(&self) -> (u32, u32) {
+        match self {
+            Zoom::Braille => (2, 4),
+            Zoom::Sextant => (2, 3),
+            Zoom::HalfBlock => (1, 2),
+            _ => (1, 1),
+        }
+    }
+
+    /// Number of cells one pixel takes, across and down
+    pub fn cells_per_pixel(&self) -> (u32, u32) {
+        match self {
+            Zoom::Wide => (2, 1),
+            Zoom::Large => (4, 2),
+            _ => (1, 1),
+        }
+    }
+
+    /// Name shown in position indicator
+    pub fn label(&self) -> &'static str {
+        match self {
+            Zoom::Braille => "1:8 braille",
+            Zoom::Sextant => "1:6 sextant",
+            Zoom::HalfBlock => "1:2 square",
+            Zoom::Normal => "1:1",
+            Zoom::Wide => "2x1",
+            Zoom::Large => "4x2",
+        }
+    }
+
+    /// Character for a group of pixels when zoomed out, bit `i` is set
+    /// when `i`-th pixel of the group (row by row) is placed
+    pub fn glyph(&self, bits: u32) -> char {
+        match self {
+            Zoom::Braille => {
+                /* Braille numbers dots down the left column first, bottom row comes last */
+                const DOTS: [u32; 8] = [0x01, 0x08, 0x02, 0x10, 0x04, 0x20, 0x40, 0x80];
+                let dots = (0..8)
+                    .filter(|i| bits & (1 << i) != 0)
+                    .fold(0, |dots, i| dots | DOTS[i]);
+
+                match dots {
+                    0 => ' ',
+                    _ => char::from_u32(0x2800 + dots).unwrap_or(' '),
+                }
+            }
+            Zoom::Sextant => match bits {
+                0 => ' ',
+                0b010101 => '\u{258C}',
+                0b101010 => '\u{2590}',
+                0b111111 => '\u{2588}',
+                /* sextant block leaves out the ones which already exist as half blocks */
+                _ => {
+                    let skipped = (bits > 0b010101) as u32 + (bits > 0b101010) as u32;
+                    char::from_u32(0x1FB00 + bits - 1 - skipped).unwrap_or(' ')
+                }
+            },
+            _ => {
+                if bits == 0 {
+                    ' '
+                } else {
+                    '\u{2588}'
+                }
+            }
+        }
+    }
+
+    /// Next closer zoom level, the closest one stays
+    pub fn closer(&self) -> Zoom {
+        let i = ZOOM_LEVELS.iter().position(|z| z == self).unwrap_or(0);
+        ZOOM_LEVELS[(i + 1).min(ZOOM_LEVELS.len() - 1)]
+    }
+
+    /// Next farther zoom level, the farthest one stays
+    pub fn farther(&self) -> Zoom {
+        let i = ZOOM_LEVELS.iter().position(|z| z == self).unwrap_or(0);
+        ZOOM_LEVELS[i.saturating_sub(1)]
+    }
+}
+
+/// Part of canvas shown on screen, it's as large as the terminal.
+/// First row holds help text and the last one position indicator,
+/// canvas is shown between them starting at `x` and `y`
+pub struct Viewport {
+    pub x: u32,
+    pub y: u32,
+    pub width: u16,
+    pub height: u16,
+    pub zoom: Zoom,
+}
+
+impl Viewport {
+    /// Number of screen rows canvas is shown in
+    pub fn rows(&self) -> u16 {
+        self.height.saturating_sub(2)
+    }
+
+    /// Number of canvas pixels which fit on screen, across and down
+    pub fn span(&self) -> (u32, u32) {
+        let (pixel_width, pixel_height) = self.zoom.pixels_per_cell();
+        let (cell_width, cell_height) = self.zoom.cells_per_pixel();
+
+        (
+            self.width as u32 / cell_width * pixel_width,
+            self.rows() as u32 / cell_height * pixel_height,
+        )
+    }
+
+    /// Screen position of a canvas pixel (its top left cell), `None` if it's scrolled away
+    pub fn to_screen(&self, position: (u32, u32)) -> Option<(u16, u16)> {
+        let span = self.span();
+        if position.0 < self.x
+            || position.1 < self.y
+            || position.0 >= self.x + span.0
+            || position.1 >= self.y + span.1
+        {
+            return None;
+        }
+
+        let (pixel_width, pixel_height) = self.zoom.pixels_per_cell();
+        let (cell_width, cell_height) = self.zoom.cells_per_pixel();
+        let column = (position.0 - self.x) / pixel_width * cell_width;
+        let row = (position.1 - self.y) / pixel_height * cell_height;
+
+        Some((column as u16, row as u16 + 1))
+    }
+
+    /// Canvas position of a screen cell, `None` for first and last row.
+    /// When zoomed out, it's the top left pixel of the cell
+    pub fn to_canvas(&self, column: u16, row: u16) -> Option<(u32, u32)> {
+        if row == 0 || row > self.rows() || column >= self.width {
+            return None;
+        }
+
+        let (pixel_width, pixel_height) = self.zoom.pixels_per_cell();
+        let (cell_width, cell_height) = self.zoom.cells_per_pixel();
+        let x = column as u32 / cell_width * pixel_width;
+        let y = (row as u32 - 1) / cell_height * pixel_height;
+
+        let span = self.span();
+        if x >= span.0 || y >= span.1 {
+            return None;
+        }
+
+        Some((self.x + x, self.y + y))
+    }
+
+    /// Scroll just enough for `position` to be visible
+    pub fn follow(&mut self, canvas: &variables::Canvas, position: (u32, u32)) {
+        /* when zoomed out, scrolling goes by whole cells so dots don't jump around */
+        let (pixel_width, pixel_height) = self.zoom.pixels_per_cell();
+        let (width, rows) = self.span();
+        let (columns, rows) = ((width / pixel_width).max(1), (rows / pixel_height).max(1));
+
+        let (column, row) = (position.0 / pixel_width, position.1 / pixel_height);
+        let (mut first_column, mut first_row) = (self.x / pixel_width, self.y / pixel_height);
+
+        if column < first_column {
+            first_column = column;
+        } else if column >= first_column + columns {
+            first_column = column + 1 - columns;
+        }
+
+        if row < first_row {
+            first_row = row;
+        } else if row >= first_row + rows {
+            first_row = row + 1 - rows;
+        }
+
+        self.x = first_column * pixel_width;
+        self.y = first_row * pixel_height;
+        self.clamp(canvas);
+    }
+
+    /// Scroll so `position` is in the middle of screen, used when jumping and zooming
+    pub fn center(&mut self, canvas: &variables::Canvas, position: (u32, u32)) {
+        let (pixel_width, pixel_height) = self.zoom.pixels_per_cell();
+        let (width, rows) = self.span();
+
+        self.x = position.0.saturating_sub(width / 2);
+        self.y = position.1.saturating_sub(rows / 2);
+        self.x -= self.x % pixel_width;
+        self.y -= self.y % pixel_height;
+
+        self.clamp(canvas);
+    }
+
+    /// Don't scroll past the right and bottom edge of canvas
+    fn clamp(&mut self, canvas: &variables::Canvas) {
+        let (pixel_width, pixel_height) = self.zoom.pixels_per_cell();
+        let (width, rows) = self.span();
+
+        /* last cell might be only partly covered by canvas */
+        let canvas_width = (canvas.width as u32).div_ceil(pixel_width) * pixel_width;
+        let canvas_height = (canvas.height as u32).div_ceil(pixel_height) * pixel_height;
+
+        self.x = self.x.min(canvas_width.saturating_sub(width));
+        self.y = self.y.min(canvas_height.saturating_sub(rows));
+    }
+}
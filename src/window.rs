@@ -1,372 +1,411 @@
-use std::{
-    fs,
-    io::{Read, Stdout, Write},
-    path::Path
-};
-
-use crossterm::{
-    cursor,
-    style,
-    QueueableCommand,
-};
-
-use image::{
-    open, GenericImageView,
-    Pixel, Rgb, RgbImage,
-};
+use std::path::Path;
 
+use crossterm::{event::KeyCode, style};
+
+use crate::ansi;
+use crate::export;
+use crate::file;
+use crate::import;
 use crate::paint;
+use crate::palette;
+use crate::render;
+use crate::selection;
+use crate::timeline;
 use crate::variables;
+use crate::vga;
+use crate::viewport;
+
+/// Entries of file window
+#[derive(Clone, Copy, PartialEq)]
+pub enum FileAction {
+    OpenText,
+    SaveText,
+    OpenPng,
+    SavePng,
+    SaveAnimation,
+    OpenAnsi,
+    SaveAnsi,
+    SaveVga,
+    OpenPalette,
+    SavePalette,
+}
 
-/// Window showing how to use GVPaint
-pub fn help_window(
-    stdout: &mut Stdout,
-    canvas: &mut variables::Canvas,
-    runtime: &mut variables::Runtime,
-    state: &mut variables::State,
+/// Smallest terminal all windows fit in, file dialog is the largest one
+pub const MIN_WIDTH: u16 = 66;
+pub const MIN_HEIGHT: u16 = 20;
+
+/// Check if terminal is too small for windows
+pub fn too_small(viewport: &viewport::Viewport) -> bool {
+    viewport.width < MIN_WIDTH || viewport.height < MIN_HEIGHT
+}
+
+/// Content of help window
+const HELP_LINES: &[&str] = &[
+    "W - move cursor up",
+    "S - move cursor down",
+    "A - move cursor left",
+    "D - move cursor right",
+    "F - open 'file window'",
+    "H - open 'help window', this one",
+    "P - place a block",
+    "E - erase a block",
+    "B - fill an area, N - toggle diagonal fill",
+    "L / O / I - line / rectangle / ellipse,",
+    "    same key again - filled, P - confirm",
+    "M - mark a selection, M / P again - finish",
+    "C / X / V - copy / cut / paste, G - move,",
+    "    P - stamp pasted blocks, Q - cancel",
+    "U - undo",
+    "R - redo",
+    "J - jump to a position",
+    "K - palette: 256 colors, RGB and HSV",
+    "Y - layers: add, reorder, merge, lock",
+    "T - timeline: frames, onion skin, playback",
+    ", / . - previous / next frame",
+    "+ / - zoom in / out (overview below 1:1)",
+    "Q - exit a program or close a window",
+    "1 - 0 - change color, [ / ] - palette page",
+    "Mouse: left - place, right - erase",
+    " ",
+    "Made with Rust and thanks to StjepanBM1",
+];
+
+/// Print lines one below another starting at given position
+pub fn print_lines(
+    renderer: &mut render::Renderer,
+    x: u16,
+    y: u16,
+    lines: &[&str],
+    color: style::Color,
 ) {
-    if state.window_open == true && state.window_open_name == "help" {
+    for (i, line) in lines.iter().enumerate() {
+        renderer.text(x, y + i as u16, line, color);
+    }
+}
+
+/// Open or close window with given name, other open window is replaced
+pub fn toggle_window(state: &mut variables::State, name: &str) {
+    if state.window_open && state.window_open_name == name {
         /* close */
         state.window_open = false;
-        paint::redraw_canvas(stdout, runtime);
 
         return;
     }
 
     state.window_open = true;
-    state.window_open_name = "help".to_string();
-
-    stdout.queue(style::SetForegroundColor(style::Color::DarkGreen));
-    stdout.queue(cursor::MoveTo(
-        canvas.width - canvas.width + 5,
-        canvas.height - 18,
-    ));
-    println!("--- --- --- --- --- --- --- --- --- --- --- ---");
-
-    let mut i = 17;
-    while i != 2 {
-        stdout.queue(cursor::MoveTo(
-            canvas.width - canvas.width + 4,
-            canvas.height - i,
-        ));
-        println!("|");
-        i -= 1;
-    }
+    state.window_open_name = name.to_string();
+}
 
-    let mut j = 17;
-    while j != 2 {
-        stdout.queue(cursor::MoveTo(
-            canvas.width - canvas.width + 52,
-            canvas.height - j,
-        ));
-        println!("|");
-        j -= 1;
+/// Window showing how to use GVPaint
+pub fn help_window(state: &mut variables::State) {
+    toggle_window(state, "help");
+}
+
+/// Draw help window, it grows upwards from bottom of screen.
+/// On short terminals it starts at the top and some lines are left out
+pub fn draw_help_window(renderer: &mut render::Renderer, viewport: &viewport::Viewport) {
+    let mut lines: Vec<&str> = HELP_LINES.to_vec();
+    let room = viewport.height.saturating_sub(3) as usize;
+    if lines.len() + 3 > room {
+        lines.truncate(room.saturating_sub(4));
+        lines.push("... make terminal taller to see more");
     }
 
-    stdout.queue(cursor::MoveTo(
-        canvas.width - canvas.width + 5,
-        canvas.height - 3,
-    ));
-    println!("--- --- --- --- --- --- --- --- --- --- --- ---");
-
-    stdout.queue(cursor::MoveTo(
-        canvas.width - canvas.width + 6,
-        canvas.height - 17,
-    ));
-    stdout.queue(style::SetForegroundColor(style::Color::Red));
-    println!("Keyboard shortcuts: ");
-    println!("\t W - move cursor up");
-    println!("\t S - move cursor down");
-    println!("\t A - move cursor left");
-    println!("\t D - move cursor right");
-    println!("\t F - open 'file window'");
-    println!("\t H - open 'help window', this one");
-    println!("\t P - place a block");
-    println!("\t E - erase a block");
-    println!("\t Q - exit a program or close a window");
-    println!("\t 1 - 9 - change color");
-    println!(" ");
-    println!("\t Made with Rust and thanks to StjepanBM1");
+    let height = lines.len() as u16 + 3;
+    let y = viewport.height.saturating_sub(2 + height).max(1);
+    draw_border(renderer, 4, y, 49, height);
+
+    print_lines(renderer, 6, y + 1, &["Keyboard shortcuts: "], style::Color::Red);
+    print_lines(renderer, 9, y + 2, &lines, style::Color::Red);
 }
 
 /// Window for opening and saving files
-pub fn file_window(
-    stdout: &mut Stdout,
-    canvas: &mut variables::Canvas,
-    runtime: &mut variables::Runtime,
-    state: &mut variables::State,
-) {
-    if state.window_open == true && state.window_open_name == "file" {
-        /* close */
-        state.window_open = false;
-        paint::redraw_canvas(stdout, runtime);
-
-        return;
-    }
-
-    state.window_open = true;
-    state.window_open_name = "file".to_string();
-
-    stdout.queue(style::SetForegroundColor(style::Color::DarkGreen));
-    stdout.queue(cursor::MoveTo(
-        canvas.width - canvas.width + 5,
-        canvas.height - 16,
-    ));
-    println!("--- --- --- --- --- --- --- --- --- ---");
-
-    let mut i = 15;
-    while i != 3 {
-        stdout.queue(cursor::MoveTo(
-            canvas.width - canvas.width + 4,
-            canvas.height - i,
-        ));
-        println!("|");
-        i -= 1;
-    }
+pub fn file_window(state: &mut variables::State) {
+    toggle_window(state, "file");
+}
 
-    let mut j = 15;
-    while j != 3 {
-        stdout.queue(cursor::MoveTo(
-            canvas.width - canvas.width + 44,
-            canvas.height - j,
-        ));
-        println!("|");
-        j -= 1;
-    }
+/// Draw file window with its entries
+pub fn draw_file_window(renderer: &mut render::Renderer, viewport: &viewport::Viewport) {
+    draw_border(renderer, 4, viewport.height.saturating_sub(16), 41, 14);
+
+    print_lines(
+        renderer,
+        9,
+        viewport.height.saturating_sub(13),
+        &[
+            "Open text file...",
+            "Save as text file...",
+            "Open .png file...",
+            "Save as .png...",
+            "Save animation (.gif, .png)...",
+            "Open ANSI art...",
+            "Save as ANSI art...",
+            "Save VGA text buffer...",
+            "Load palette...",
+            "Save palette...",
+        ],
+        style::Color::Red,
+    );
+}
 
-    stdout.queue(cursor::MoveTo(
-        canvas.width - canvas.width + 5,
-        canvas.height - 3,
-    ));
-    println!("--- --- --- --- --- --- --- --- --- ---");
-
-    stdout.queue(cursor::MoveTo(
-        canvas.width - canvas.width + 10,
-        canvas.height - 10,
-    ));
-    stdout.queue(style::SetForegroundColor(style::Color::Red));
-    println!(" ");
-    println!("\t Open text file");
-    println!("\t Save as text file");
-    println!("\t Open .png file (todo)");
-    println!("\t Save as .png (todo)");
+/// Window asking for a position cursor should jump to
+pub fn jump_window(state: &mut variables::State) {
+    state.jump.clear();
+    toggle_window(state, "jump");
 }
 
-/// Return a content of a file
-fn content_in_file(file_menu: &mut variables::FileMenu, filename: &str) -> std::io::Result<()> {
-    let cwd = std::env::current_dir().unwrap();
-    /*let cwd_str = cwd.into_os_string().into_string();
-    println!("{:?}", cwd_str);*/
+/// Draw jump window with typed position
+pub fn draw_jump_window(
+    renderer: &mut render::Renderer,
+    viewport: &viewport::Viewport,
+    state: &variables::State,
+) {
+    let y = viewport.height.saturating_sub(9);
+    draw_border(renderer, 4, y, 41, 7);
+
+    print_lines(renderer, 6, y + 1, &["Jump to position"], style::Color::Red);
+    print_lines(
+        renderer,
+        6,
+        y + 3,
+        &[&format!("X Y: {}_", state.jump)],
+        style::Color::DarkYellow,
+    );
+    print_lines(
+        renderer,
+        6,
+        y + 5,
+        &["Enter - jump, Esc - cancel"],
+        style::Color::DarkGreen,
+    );
+}
 
-    let mut file = fs::File::open(filename)?;
-    file.read_to_string(&mut file_menu.file_content)?;
+/// Parse position typed in jump window, `X Y` or `X,Y`
+fn parse_position(text: &str) -> Option<(u32, u32)> {
+    let fields: Vec<&str> = text
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|field| !field.is_empty())
+        .collect();
 
-    Ok(())
+    match fields[..] {
+        [x, y] => Some((x.parse().ok()?, y.parse().ok()?)),
+        _ => None,
+    }
 }
 
-/// Write content to a file
-fn write_to_file(new_file: bool, filename: &str, string: String) -> std::io::Result<()> {
-    if new_file {
-        let mut file = fs::File::create(filename)?;
-        file.write_all(string.as_bytes())?;
-    } else {
-        let mut file2 = fs::OpenOptions::new()
-            .write(true)
-            .append(true)
-            .open(filename)
-            .unwrap();
-
-        if let Err(e) = write!(file2, "{}", string) {
-            eprintln!("Couldn't write to file: {}", e);
+/// Handle a key pressed while jump window is open, cursor is moved
+/// to typed position and screen is scrolled around it
+pub fn jump_input(
+    canvas: &variables::Canvas,
+    runtime: &mut variables::Runtime,
+    state: &mut variables::State,
+    code: KeyCode,
+) {
+    match code {
+        KeyCode::Esc => state.window_open = false,
+        KeyCode::Enter => {
+            state.window_open = false;
+
+            let Some((x, y)) = parse_position(&state.jump) else {
+                let message = format!(
+                    "`{}` is not a position, type X and Y, e.g. 120 40",
+                    state.jump
+                );
+                message_box(state, "Jump", &message);
+                return;
+            };
+
+            runtime.cursor_x = x.min(canvas.width as u32 - 1) as f64;
+            runtime.cursor_y = y.min(canvas.height as u32 - 1) as f64;
+
+            let cursor = (runtime.cursor_x as u32, runtime.cursor_y as u32);
+            runtime.viewport.center(canvas, cursor);
+            selection::follow_cursor(runtime);
+        }
+        KeyCode::Backspace => {
+            state.jump.pop();
         }
+        KeyCode::Char(c) if c.is_ascii_digit() || c == ' ' || c == ',' => state.jump.push(c),
+        _ => {}
     }
-
-    Ok(())
 }
 
-/// Convert crossterm::style::Color to image::Rgb<u8>
-fn crossterm_to_image_color(color: style::Color) -> Rgb<u8> {
-    match color {
-        style::Color::Black => Rgb([0, 0, 0]),
-        style::Color::DarkGrey => Rgb([64, 64, 64]),
-        style::Color::Grey => Rgb([128, 128, 128]),
-        style::Color::White => Rgb([255, 255, 255]),
-        style::Color::Red => Rgb([255, 0, 0]),
-        style::Color::DarkRed => Rgb([128, 0, 0]),
-        style::Color::Green => Rgb([0, 255, 0]),
-        style::Color::DarkGreen => Rgb([0, 128, 0]),
-        style::Color::Blue => Rgb([0, 0, 255]),
-        style::Color::DarkBlue => Rgb([0, 0, 128]),
-        style::Color::Yellow => Rgb([255, 255, 0]),
-        style::Color::DarkYellow => Rgb([128, 128, 0]),
-        style::Color::Magenta => Rgb([255, 0, 255]),
-        style::Color::DarkMagenta => Rgb([128, 0, 128]),
-        style::Color::Cyan => Rgb([0, 255, 255]),
-        style::Color::DarkCyan => Rgb([0, 128, 128]),
-        style::Color::Rgb { r, g, b } => Rgb([r, g, b]),
-        _ => Rgb([0, 0, 0])
+/// Return file window's entry at given position, used by both cursor and mouse
+pub fn file_window_entry(viewport: &viewport::Viewport, x: u16, y: u16) -> Option<FileAction> {
+    if !(5..44).contains(&x) {
+        return None;
+    }
+
+    match viewport.height.checked_sub(y) {
+        Some(13) => Some(FileAction::OpenText),
+        Some(12) => Some(FileAction::SaveText),
+        Some(11) => Some(FileAction::OpenPng),
+        Some(10) => Some(FileAction::SavePng),
+        Some(9) => Some(FileAction::SaveAnimation),
+        Some(8) => Some(FileAction::OpenAnsi),
+        Some(7) => Some(FileAction::SaveAnsi),
+        Some(6) => Some(FileAction::SaveVga),
+        Some(5) => Some(FileAction::OpenPalette),
+        Some(4) => Some(FileAction::SavePalette),
+        _ => None,
     }
 }
 
-/// Convert image::Rgb<u8> to crossterm::style::Color
-fn rgb_to_crossterm_color(rgb: Rgb<u8>) -> style::Color {
-    style::Color::Rgb {
-        r: rgb[0],
-        g: rgb[1],
-        b: rgb[2],
+/// Draw a window border, `width` and `height` include the border itself.
+/// Inside of the window is cleared, so painting doesn't show through
+pub fn draw_border(renderer: &mut render::Renderer, x: u16, y: u16, width: u16, height: u16) {
+    let line = "--- ".repeat((width as usize - 1) / 4);
+    let line = line.trim_end();
+    renderer.text(x + 1, y, line, style::Color::DarkGreen);
+    renderer.text(x + 1, y + height - 1, line, style::Color::DarkGreen);
+
+    for i in 1..height - 1 {
+        renderer.text(x, y + i, "|", style::Color::DarkGreen);
+        renderer.text(x + 1, y + i, &" ".repeat(width as usize - 2), style::Color::White);
+        renderer.text(x + width - 1, y + i, "|", style::Color::DarkGreen);
     }
 }
-/// Actions for file window
+
+/// Actions for file window. Opened files are added to painting
+/// and canvas grows if they don't fit in it, loaded palette replaces the current one
 pub fn file_window_actions(
-    stdout: &mut Stdout,
     canvas: &mut variables::Canvas,
     runtime: &mut variables::Runtime,
     state: &mut variables::State,
     file_menu: &mut variables::FileMenu,
+    action: FileAction,
+    path: &Path,
 ) {
-    let max_x = 40;
-
-    /*
-     * Open text file
-     * Save text file
-     * Open .png file
-     * Save as png file
-     */
-    if (runtime.cursor_x as u16 >= 23
-        && runtime.cursor_x as u16 <= max_x
-        && runtime.cursor_y as u16 == canvas.height - 9)
-    {
-        content_in_file(file_menu, "painting.txt");
-
-        if (file_menu.file_content == " ") {
-            let strings: [&str; 6] = [
-                "Make sure you have `painting.txt` in",
-                "working folder!",
-                "If you are running directly from GitHub",
-                "repo, enter repo root folder",
-                "and there create `painting.txt` file.",
-                " ",
-            ];
-            let mut i = 15;
-            let mut j = 0;
-
-            while (i >= 10) {
-                stdout.queue(cursor::MoveTo(5, canvas.height - i));
-                stdout.queue(style::SetForegroundColor(style::Color::DarkYellow));
-                println!("{}", strings[j]);
-
-                i -= 1;
-                j += 1;
-            }
-        } else {
-            let vec: Vec<u32> = file_menu
-                .file_content
-                .split_whitespace()
-                .map(|s| s.parse().expect("Parsing error!"))
-                .collect();
-
-            let current_x = runtime.cursor_x;
-            let current_y = runtime.cursor_y;
-            let current_color = runtime.color;
-
-            let mut temp_x = current_x;
-            let mut temp_y = current_y;
-            let mut temp_color = current_color;
-
-            let mut iterator = 1;
-
-            for i in vec {
-                match iterator {
-                    1 => {
-                        temp_x = i as f64;
-                        iterator += 1;
-                    }
-                    2 => {
-                        temp_y = i as f64;
-                        iterator += 1;
-                    }
-                    3 => {
-                        unsafe {
-                            temp_color = paint::return_color(std::mem::transmute(i as u8));
-                        }
-
-                        runtime.cursor_x = temp_x;
-                        runtime.cursor_y = temp_y;
-                        runtime.color = temp_color;
-
-                        paint::place_blok(stdout, runtime);
-
-                        runtime.cursor_x = current_x;
-                        runtime.cursor_y = current_y;
-                        runtime.color = current_color;
-
-                        temp_x = 0 as f64;
-                        temp_y = 0 as f64;
-                        temp_color = style::Color::Black;
-
-                        iterator = 1;
-                    }
-
-                    _ => { /* ignore */ }
-                }
-            }
+    let result = match action {
+        FileAction::OpenText => file::read_text(path, None).map(|document| {
+            paint::grow_canvas(canvas, document.extent());
+            timeline::load_frames(runtime, state, document);
+        }),
+        FileAction::SaveText => file::save_text(
+            path,
+            &runtime.layers.flatten_frames(),
+            runtime.layers.fps,
+            true,
+        ),
+        FileAction::OpenPng => {
+            import::import_window(state, file_menu, path);
+            Ok(())
         }
-    } else if (runtime.cursor_x as u16 >= 26
-        && runtime.cursor_x as u16 <= max_x
-        && runtime.cursor_y as u16 == canvas.height - 8)
-    {
-        if Path::new("painting.txt").exists() {
-            fs::rename("painting.txt", "painting2.txt")
-                .expect("Couldn't rename `painting.txt` to `painting2.txt`!");
+        FileAction::SavePng => {
+            export::export_window(state, file_menu, path, false);
+            Ok(())
         }
+        FileAction::SaveAnimation => {
+            export::export_window(state, file_menu, path, true);
+            Ok(())
+        }
+        FileAction::OpenAnsi => {
+            ansi::ansi_window(state, file_menu, path, true);
+            Ok(())
+        }
+        FileAction::SaveAnsi => {
+            ansi::ansi_window(state, file_menu, path, false);
+            Ok(())
+        }
+        FileAction::SaveVga => {
+            vga::vga_window(state, file_menu, path);
+            Ok(())
+        }
+        FileAction::OpenPalette => {
+            file::read_palette(path).map(|colors| palette::load(runtime, colors))
+        }
+        FileAction::SavePalette => file::save_palette(path, &runtime.palette.colors),
+    };
+
+    if let Err(e) = result {
+        message_box(state, "Error", &e.to_string());
+    }
+}
+
+/// Split text into lines which are at most `width` characters long
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    text.lines()
+        .flat_map(|paragraph| wrap_paragraph(paragraph, width))
+        .collect()
+}
 
-        let mut new_file = true;
-        let mut placed = runtime.placed.clone();
-        for (k, v) in placed {
-            let mut options = fs::OpenOptions::new();
-            let mut file = options.write(true).open("painting.txt");
+/// Split a single paragraph of text, see `wrap`
+fn wrap_paragraph(text: &str, width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
 
-            let kcolor = paint::return_color_int(v);
-            let icolor = kcolor as u32;
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
 
-            let mut string: String = k.0.to_string()
-                + " "
-                + &k.1.to_string()
-                + " "
-                + &icolor.to_string()
-                + "\n";
+        /* words longer than a line (usually paths) are split too */
+        while word.len() > width {
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            lines.push(word.drain(..width).collect());
+        }
 
-            write_to_file(new_file, "painting.txt", string);
-            new_file = false;
+        let word: String = word.into_iter().collect();
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
         }
-    } else if (runtime.cursor_x as u16 >= 22
-        && runtime.cursor_x as u16 <= max_x
-        && runtime.cursor_y as u16 == canvas.height - 7)
-    {
-        let img = open("painting.png").expect("Failed to open image");
-        let rgb_img = img.to_rgb8();
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line += &word;
+    }
 
-        for (x, y, pixel) in rgb_img.enumerate_pixels() {
-            let color = pixel.to_rgb(); // Extract the color as an RGB value
+    if !line.is_empty() {
+        lines.push(line);
+    }
 
-            runtime.cursor_x = x as f64;
-            runtime.cursor_y = y as f64;
-            runtime.color = rgb_to_crossterm_color(color.to_rgb());
+    lines
+}
 
-            paint::place_blok(stdout, runtime);
-        }
+/// Window showing a message, e.g. why a file couldn't be opened.
+/// Any key closes it
+pub fn message_box(state: &mut variables::State, title: &str, message: &str) {
+    state.window_open = true;
+    state.window_open_name = "message".to_string();
+    state.message = Some((title.to_string(), message.to_string()));
+}
 
-        runtime.cursor_color = style::Color::DarkRed;
-    } else if (runtime.cursor_x as u16 >= 28
-        && runtime.cursor_x as u16 <= max_x
-        && runtime.cursor_y as u16 == canvas.height - 6)
-    {
-        let mut img = RgbImage::new(canvas.width as u32, canvas.height as u32);
+/// Draw message box above bottom of screen
+pub fn draw_message_box(
+    renderer: &mut render::Renderer,
+    viewport: &viewport::Viewport,
+    state: &variables::State,
+) {
+    let Some((title, message)) = state.message.as_ref() else {
+        return;
+    };
 
-        for (k, v) in runtime.placed.clone() {
-            img.put_pixel(k.0, k.1, crossterm_to_image_color(v));
-        }
+    let width: u16 = 49;
+    let mut lines = wrap(message, width as usize - 6);
 
-        img.save("painting.png").expect("Failed to save image");
+    /* long messages (e.g. many parsing errors) are cut to fit on screen */
+    let max_lines = (viewport.height as usize).saturating_sub(12).max(1);
+    if lines.len() > max_lines {
+        let hidden = lines.len() - max_lines + 1;
+        lines.truncate(max_lines - 1);
+        lines.push(format!("... and {} more lines", hidden));
     }
+
+    let height = lines.len() as u16 + 6;
+    let y = viewport.height.saturating_sub(2 + height);
+
+    draw_border(renderer, 4, y, width, height);
+
+    print_lines(renderer, 6, y + 1, &[title], style::Color::Red);
+
+    let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+    print_lines(renderer, 7, y + 3, &lines, style::Color::DarkYellow);
+
+    print_lines(
+        renderer,
+        6,
+        y + height - 2,
+        &["Press any key to continue"],
+        style::Color::DarkGreen,
+    );
 }